@@ -0,0 +1,119 @@
+use std::time::Duration;
+use criterion::{criterion_group, criterion_main, Criterion, SamplingMode, black_box};
+use ground_risk::risks::risks::{RiskMap, Coord, corridor_pixels};
+use ground_risk::bicriteria_dijkstra::bicriteria_dijkstra::{BicriteriaDijkstraInstance, Path};
+use ground_risk::air_risks::air_risks::AirRiskInstance;
+use ground_risk::legend::legend::{DensityEncoding, load_map_from_image};
+use ground_risk::grid_io::grid_io::load_grid;
+use ground_risk::grid::grid::Grid;
+use ground_risk::waypoint_graph::waypoint_graph::{WaypointGraph, lattice_waypoints};
+
+// The bundled map and the origin and destination of the main program
+const ORIGIN: Coord<i16> = Coord{x: 517, y: 412};
+const DESTINATION: Coord<i16> = Coord{x: 765, y: 600};
+
+fn bundled_risk_map() -> RiskMap {
+    let encoding = DensityEncoding::from_file("./data/legend_nk.json");
+
+    RiskMap{
+        map: load_map_from_image("./data/density_fixed_scaled.png", &encoding),
+        m_per_pixel: 1000.0/(131.0/2.0),
+        offset: 25,
+        no_fly_zones: None,
+        penalty_zones: None
+    }
+}
+
+// All the edges of the default neighbourhood from one cell, every orientation once
+fn edge_ends() -> Vec<Coord<i16>> {
+    (-5..=5).flat_map(|dx| (-5..=5).map(move |dy| Coord{x: ORIGIN.x + dx, y: ORIGIN.y + dy}))
+        .filter(|p| *p != ORIGIN)
+        .collect()
+}
+
+fn rasterisation(c: &mut Criterion) {
+    let risk_map = bundled_risk_map();
+    let ends = edge_ends();
+
+    c.bench_function("corridor_pixels", |b| b.iter(|| {
+        ends.iter().map(|p| corridor_pixels(ORIGIN, *p, black_box(150.0), risk_map.m_per_pixel).len()).sum::<usize>()
+    }));
+
+    c.bench_function("risk", |b| b.iter(|| {
+        ends.iter().map(|p| risk_map.risk(ORIGIN, *p, black_box(150.0))).sum::<i32>()
+    }));
+}
+
+fn air_risk(c: &mut Criterion) {
+    let map = load_grid("./data/map.json").unwrap();
+    let air_risk_instance = AirRiskInstance::new(map.transposed(), 4*7*24);
+    let path = Path{
+        path: vec![DESTINATION, Coord{x: 700, y: 450}, Coord{x: 600, y: 420}, ORIGIN],
+        linear_combination_weight: 0.0,
+        risk: 0,
+        length_m: 0.0,
+        alpha: 0.0,
+        altitude_profile: None
+    };
+
+    c.bench_function("compute_air_risk", |b| b.iter(|| air_risk_instance.compute_air_risk(black_box(&path))));
+}
+
+// A 100 x 100 pixel part of the bundled map around the origin, on which a whole front on the grid
+// takes seconds instead of the hours it takes on the full map. The part lies well within the offset
+// of the full map, so none of it is cut off here either.
+fn bundled_risk_map_part() -> RiskMap {
+    let risk_map = bundled_risk_map();
+    let rows = (380..480).map(|row| (480..580).map(|col| risk_map.map.get(row, col)).collect()).collect();
+
+    RiskMap{
+        map: Grid::from_rows(rows),
+        offset: 0,
+        ..risk_map
+    }
+}
+
+// The search of the main program on the whole bundled map. It takes over a minute,
+// so it only runs with FULL_MAP_BENCH set.
+fn full_map_search(c: &mut Criterion) {
+    if std::env::var_os("FULL_MAP_BENCH").is_none() {
+        return
+    }
+
+    let risk_map = bundled_risk_map();
+    let inst = BicriteriaDijkstraInstance::new(&risk_map, ORIGIN, DESTINATION, 5, 150.0);
+
+    let mut group = c.benchmark_group("full_map");
+    group.sample_size(10).sampling_mode(SamplingMode::Flat).measurement_time(Duration::from_secs(600));
+
+    group.bench_function("run_with_alpha", |b| b.iter(|| inst.run_with_alpha(black_box(0.0002))));
+
+    group.finish();
+}
+
+fn search(c: &mut Criterion) {
+    let risk_map = bundled_risk_map_part();
+    // The bundled origin and a destination 50 pixels south-east of it
+    let (from, to) = (Coord{x: 37, y: 32}, Coord{x: 72, y: 68});
+    let inst = BicriteriaDijkstraInstance::new(&risk_map, from, to, 5, 150.0);
+
+    let mut group = c.benchmark_group("search");
+    // A search takes a second, a front about ten, hence the few samples of one iteration each
+    group.sample_size(10).sampling_mode(SamplingMode::Flat).measurement_time(Duration::from_secs(100));
+
+    group.bench_function("run_with_alpha", |b| b.iter(|| inst.run_with_alpha(black_box(0.0002))));
+    group.bench_function("compute_pareto_apx_paths", |b| b.iter(|| inst.compute_pareto_apx_paths()));
+
+    // The front on a waypoint lattice, as with --waypoints=lattice:8
+    let mut waypoints = lattice_waypoints(&risk_map, 8);
+    waypoints.extend([from, to]);
+    let graph = WaypointGraph::new(&risk_map, waypoints, 24.0, 150.0);
+    let graph_inst = BicriteriaDijkstraInstance::new(&risk_map, from, to, 5, 150.0).with_waypoint_graph(&graph);
+
+    group.bench_function("compute_pareto_apx_paths_waypoints", |b| b.iter(|| graph_inst.compute_pareto_apx_paths()));
+
+    group.finish();
+}
+
+criterion_group!(benches, rasterisation, air_risk, search, full_map_search);
+criterion_main!(benches);
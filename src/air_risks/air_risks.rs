@@ -1,30 +1,226 @@
+#[cfg(test)]
+#[path = "tests.rs"]
+mod tests;
+
 use crate::bicriteria_dijkstra::bicriteria_dijkstra::Path;
 use bresenham::Bresenham;
+use crate::grid::grid::Grid;
+use crate::risks::risks::{Coord, corridor_pixels};
+use serde::{Serialize, Deserialize};
+
+// Altitudes of manned traffic that matter for drones, e.g. 0-500 m above ground
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct AltitudeBand {
+    pub min_m: f64,
+    pub max_m: f64
+}
+
+impl AltitudeBand {
+    pub fn contains(&self, alt_m: f64) -> bool {
+        self.min_m <= alt_m && alt_m <= self.max_m
+    }
+}
+
+pub struct AirRiskLayer {
+    pub band: AltitudeBand,
+    pub map: Grid<i32>
+}
+
+// Planned altitude of the drone at every waypoint of `Path::path`, interpolated linearly along the legs.
+// It is only needed for the air risk on altitude layers.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AltitudeProfile {
+    pub altitudes_m: Vec<f64>
+}
+
+impl AltitudeProfile {
+    pub fn constant(path: &Path, altitude_m: f64) -> Self {
+        Self{ altitudes_m: vec![altitude_m; path.path.len()] }
+    }
+}
+
+// Parameters of the gas model of mid-air collisions: aircraft move like gas molecules, so the expected
+// number of collisions is density x relative speed x collision cross-section x exposure time.
+// The relative speed is the RMS one over uniformly random headings, which has a closed form. The mean
+// one (an elliptic integral) is never larger and at most 10% smaller, when both speeds are equal, so
+// the estimate errs on the safe side.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct EncounterModel {
+    pub drone_speed_mps: f64,
+    pub drone_radius_m: f64,
+    pub aircraft_speed_mps: f64,
+    pub aircraft_radius_m: f64,
+    // Height of the airspace the traffic of the map is spread over
+    pub traffic_layer_thickness_m: f64
+}
+
+impl EncounterModel {
+    // RMS relative speed for aircraft headings uniformly random and independent of the drone's
+    pub fn relative_speed_mps(&self) -> f64 {
+        (self.drone_speed_mps.powi(2) + self.aircraft_speed_mps.powi(2)).sqrt()
+    }
+
+    pub fn cross_section_m2(&self) -> f64 {
+        std::f64::consts::PI * (self.drone_radius_m + self.aircraft_radius_m).powi(2)
+    }
+}
+
+// Lateral buffer around the route, in metres on either side
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LateralBuffer {
+    pub buffer_m: f64,
+    pub m_per_pixel: f64
+}
 
 pub struct AirRiskInstance {
-    pub(crate) map: Vec<Vec<i32>>,
-    total_time_s: i32
+    pub map: Grid<i32>,
+    pub total_time_s: i32,
+    pub(crate) layers: Vec<AirRiskLayer>,
+    pub(crate) lateral_buffer: Option<LateralBuffer>
 }
 
 impl AirRiskInstance {
-    pub fn new(map: Vec<Vec<i32>>, total_time_s: i32) -> Self {
-        return Self{ map, total_time_s}
+    pub fn new(map: Grid<i32>, total_time_s: i32) -> Self {
+        return Self{ map, total_time_s, layers: vec![], lateral_buffer: None}
+    }
+
+    // An instance with traffic per altitude band, `map` holds the traffic of all the bands together
+    pub fn with_layers(layers: Vec<AirRiskLayer>, total_time_s: i32) -> Self {
+        let first = layers.first().expect("At least one altitude layer is needed");
+        let mut map = Grid::new(first.map.rows(), first.map.cols(), 0);
+
+        for layer in &layers {
+            if !layer.map.same_shape(&map) {
+                panic!("All the altitude layers must have the same shape");
+            }
+
+            for row in 0..map.rows() {
+                for col in 0..map.cols() {
+                    map.set(row, col, map.get(row, col) + layer.map.get(row, col));
+                }
+            }
+        }
+
+        Self{ map, total_time_s, layers, lateral_buffer: None }
+    }
+
+    // Averages the air risk over the same kind of corridor as the ground risk instead of the
+    // single-pixel line, so that traffic next to the route is not missed
+    pub fn with_lateral_buffer(mut self, buffer_m: f64, m_per_pixel: f64) -> Self {
+        self.lateral_buffer = Some(LateralBuffer{ buffer_m, m_per_pixel });
+        self
     }
 
+    // The mean traffic along the path per unit of time. With altitude layers every pixel only counts the
+    // traffic of the band the drone is in there (the busiest one on a boundary between bands), according
+    // to the altitude profile of the path.
     pub fn compute_air_risk(&self, path: &Path) -> f64 {
         let mut air_risk = 0.0;
         let mut length_px = 0;
 
         for i in 0..&path.path.len()-1 {
+            let (traffic, leg_px) = self.leg_traffic(path, i);
+            air_risk += traffic;
+            length_px += leg_px;
+        }
+
+        return self.air_risk_of(air_risk, length_px)
+    }
+
+    // The traffic summed over the pixels of the leg from `path.path[i]` to `path.path[i+1]`, and the number of those pixels
+    pub fn leg_traffic(&self, path: &Path, i: usize) -> (f64, usize) {
+        let (s, e) = (path.path[i], path.path[i+1]);
+        let pixels = self.leg_pixels(s, e);
+
+        if self.layers.is_empty() {
+            let traffic = pixels.iter().map(|coord| self.map.at(*coord) as f64).sum();
+
+            return (traffic, pixels.len())
+        }
+
+        let profile = match &path.altitude_profile {
+            Some(profile) if profile.altitudes_m.len() == path.path.len() => profile,
+            Some(profile) => panic!("The altitude profile has {} altitudes for {} waypoints", profile.altitudes_m.len(), path.path.len()),
+            None => panic!("The air risk of a route on altitude layers needs the altitude profile of the route")
+        };
+
+        let traffic = pixels.iter()
+            .map(|coord| {
+                let f = position_along(s, e, *coord);
+                let altitude_m = profile.altitudes_m[i] + (profile.altitudes_m[i+1] - profile.altitudes_m[i]) * f;
+
+                self.traffic_at(*coord, altitude_m) as f64
+            })
+            .sum();
+
+        (traffic, pixels.len())
+    }
+
+    // The air risk of a stretch with the given summed traffic and length in pixels
+    pub fn air_risk_of(&self, traffic: f64, length_px: usize) -> f64 {
+        (traffic / length_px as f64) / self.total_time_s as f64
+    }
+
+    // Expected number of mid-air collisions of one flight along the path according to the gas model.
+    // Unlike `compute_air_risk` it grows with the length of the route and the time spent flying it.
+    // The collision cross-section already accounts for the width of the drone, so no lateral buffer is used.
+    pub fn expected_collisions(&self, path: &Path, model: &EncounterModel, m_per_pixel: f64) -> f64 {
+        let rate_per_density = model.relative_speed_mps() * model.cross_section_m2();
+        let mut collisions = 0.0;
+
+        for i in 0..path.path.len()-1 {
             let s = &path.path[i];
             let e = &path.path[i+1];
+            let pixels: Vec<(isize, isize)> = Bresenham::new((s.x as isize, s.y as isize), (e.x as isize, e.y as isize)).collect();
+            let leg_m = (((e.x - s.x) as f64).powi(2) + ((e.y - s.y) as f64).powi(2)).sqrt() * m_per_pixel;
+            let exposure_s = leg_m / pixels.len().max(1) as f64 / model.drone_speed_mps;
 
-            for (x, y) in Bresenham::new((s.x as isize, s.y as isize), (e.x as isize, e.y as isize)) {
-                air_risk += self.map[x as usize][y as usize] as f64;
-                length_px += 1;
+            for (x, y) in pixels {
+                collisions += self.aircraft_density_at(Coord{x: x as i16, y: y as i16}, model, m_per_pixel) * rate_per_density * exposure_s;
             }
         }
 
-        return (air_risk/(length_px as f64))/(self.total_time_s as f64)
+        collisions
+    }
+
+    // Aircraft per m³ in the cell. The map counts the aircraft which passed the cell during total_time_s,
+    // each of them stayed in it for the time needed to cross it.
+    fn aircraft_density_at(&self, coord: Coord<i16>, model: &EncounterModel, m_per_pixel: f64) -> f64 {
+        let passages_per_s = self.map.at(coord) as f64 / self.total_time_s as f64;
+        let mean_aircraft = passages_per_s * m_per_pixel / model.aircraft_speed_mps;
+
+        mean_aircraft / (m_per_pixel * m_per_pixel * model.traffic_layer_thickness_m)
     }
-}
\ No newline at end of file
+
+    // The pixels of the line from s to e (without e), or of the corridor around it with a lateral buffer
+    fn leg_pixels(&self, s: Coord<i16>, e: Coord<i16>) -> Vec<Coord<i16>> {
+        match self.lateral_buffer {
+            Some(buffer) => corridor_pixels(s, e, buffer.buffer_m, buffer.m_per_pixel).into_iter()
+                .filter(|c| c.x >= 0 && c.y >= 0 && (c.x as usize) < self.map.cols() && (c.y as usize) < self.map.rows())
+                .collect(),
+            None => Bresenham::new((s.x as isize, s.y as isize), (e.x as isize, e.y as isize))
+                .map(|(x, y)| Coord{x: x as i16, y: y as i16})
+                .collect()
+        }
+    }
+
+    fn traffic_at(&self, coord: Coord<i16>, altitude_m: f64) -> i32 {
+        self.layers.iter()
+            .filter(|layer| layer.band.contains(altitude_m))
+            .map(|layer| layer.map.at(coord))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+// Where the projection of the pixel falls on the segment from s (0.0) to e (1.0)
+fn position_along(s: Coord<i16>, e: Coord<i16>, coord: Coord<i16>) -> f64 {
+    let (dx, dy) = ((e.x - s.x) as f64, (e.y - s.y) as f64);
+    let length_squared = dx * dx + dy * dy;
+
+    if length_squared == 0.0 {
+        return 0.0
+    }
+
+    (((coord.x - s.x) as f64 * dx + (coord.y - s.y) as f64 * dy) / length_squared).clamp(0.0, 1.0)
+}
@@ -0,0 +1,138 @@
+#[cfg(test)]
+mod tests {
+    use super::super::{*};
+
+    fn path(waypoints: Vec<Coord<i16>>) -> Path {
+        Path{path: waypoints, linear_combination_weight: 0.0, risk: 0, length_m: 0.0, alpha: 0.0, altitude_profile: None}
+    }
+
+    fn at_altitudes(mut path: Path, altitudes_m: Vec<f64>) -> Path {
+        path.altitude_profile = Some(AltitudeProfile{altitudes_m});
+        path
+    }
+
+    fn at_altitude(path: Path, altitude_m: f64) -> Path {
+        let altitudes_m = vec![altitude_m; path.path.len()];
+        at_altitudes(path, altitudes_m)
+    }
+
+    // Traffic at 0-100 m on row 0 and at 100-200 m on row 1
+    fn layered_instance() -> AirRiskInstance {
+        AirRiskInstance::with_layers(vec![
+            AirRiskLayer{band: AltitudeBand{min_m: 0.0, max_m: 100.0}, map: Grid::from_rows(vec![vec![2, 2, 2, 2], vec![0, 0, 0, 0]])},
+            AirRiskLayer{band: AltitudeBand{min_m: 100.0, max_m: 200.0}, map: Grid::from_rows(vec![vec![0, 0, 0, 0], vec![4, 4, 4, 4]])},
+        ], 2)
+    }
+
+    #[test]
+    fn test_compute_air_risk() {
+        let instance = AirRiskInstance::new(Grid::from_rows(vec![vec![2, 4, 6, 8], vec![0, 0, 0, 0]]), 2);
+
+        // The Bresenham line does not include the last pixel
+        assert_eq!(instance.compute_air_risk(&path(vec![Coord{x: 0, y: 0}, Coord{x: 3, y: 0}])), 2.0);
+    }
+
+    #[test]
+    fn test_layers_are_summed() {
+        let instance = layered_instance();
+
+        assert_eq!(instance.map.to_rows(), vec![vec![2, 2, 2, 2], vec![4, 4, 4, 4]]);
+    }
+
+    #[test]
+    fn test_layered_air_risk() {
+        let instance = layered_instance();
+        let low_route = || path(vec![Coord{x: 0, y: 0}, Coord{x: 3, y: 0}]);
+        let high_route = || path(vec![Coord{x: 0, y: 1}, Coord{x: 3, y: 1}]);
+
+        assert_eq!(instance.compute_air_risk(&at_altitude(low_route(), 60.0)), 1.0);
+        assert_eq!(instance.compute_air_risk(&at_altitude(low_route(), 120.0)), 0.0);
+        assert_eq!(instance.compute_air_risk(&at_altitude(high_route(), 120.0)), 2.0);
+        assert_eq!(instance.compute_air_risk(&at_altitude(high_route(), 250.0)), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "needs the altitude profile")]
+    fn test_layered_air_risk_without_profile() {
+        layered_instance().compute_air_risk(&path(vec![Coord{x: 0, y: 0}, Coord{x: 3, y: 0}]));
+    }
+
+    #[test]
+    fn test_altitude_is_interpolated() {
+        let instance = layered_instance();
+
+        // At 40, 80, 120 and 160 m, only the first two pixels are in the band with traffic
+        let climbing_route = at_altitudes(path(vec![Coord{x: 0, y: 0}, Coord{x: 4, y: 0}]), vec![40.0, 200.0]);
+        assert_eq!(instance.compute_air_risk(&climbing_route), 0.5);
+    }
+
+    #[test]
+    fn test_without_layers() {
+        let instance = AirRiskInstance::new(Grid::from_rows(vec![vec![2, 4, 6, 8]]), 2);
+        let route = || path(vec![Coord{x: 0, y: 0}, Coord{x: 3, y: 0}]);
+
+        assert_eq!(instance.compute_air_risk(&at_altitude(route(), 500.0)), instance.compute_air_risk(&route()));
+    }
+
+    const MODEL: EncounterModel = EncounterModel{
+        drone_speed_mps: 15.0,
+        drone_radius_m: 1.0,
+        aircraft_speed_mps: 60.0,
+        aircraft_radius_m: 9.0,
+        traffic_layer_thickness_m: 100.0
+    };
+
+    #[test]
+    fn test_expected_collisions() {
+        // 36 aircraft passing every 10 m cell in an hour
+        let instance = AirRiskInstance::new(Grid::new(3, 10, 36), 3600);
+        let route = path(vec![Coord{x: 0, y: 0}, Coord{x: 5, y: 0}]);
+
+        // 0.01 aircraft/s staying 10/60 s in a 10 x 10 x 100 m cell
+        let density = 0.01 * 10.0 / 60.0 / 10000.0;
+        let expected = density * MODEL.relative_speed_mps() * std::f64::consts::PI * 100.0 * 50.0 / 15.0;
+
+        assert!((instance.expected_collisions(&route, &MODEL, 10.0) - expected).abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_collisions_grow_with_exposure() {
+        let instance = AirRiskInstance::new(Grid::new(10, 10, 5), 3600);
+        let short_route = path(vec![Coord{x: 0, y: 0}, Coord{x: 4, y: 4}]);
+        let long_route = path(vec![Coord{x: 0, y: 0}, Coord{x: 4, y: 4}, Coord{x: 8, y: 8}]);
+
+        let short = instance.expected_collisions(&short_route, &MODEL, 10.0);
+        let long = instance.expected_collisions(&long_route, &MODEL, 10.0);
+        assert!((long - 2.0 * short).abs() < 1e-15);
+
+        // The mean cell value does not see the difference
+        assert_eq!(instance.compute_air_risk(&short_route), instance.compute_air_risk(&long_route));
+    }
+
+    #[test]
+    fn test_lateral_buffer() {
+        // Traffic on row 3 next to a route along row 2
+        let mut map = Grid::new(6, 8, 0);
+        for x in 0..8 {
+            map.set(3, x, 10);
+        }
+        let route = path(vec![Coord{x: 1, y: 2}, Coord{x: 6, y: 2}]);
+
+        assert_eq!(AirRiskInstance::new(map.clone(), 1).compute_air_risk(&route), 0.0);
+
+        let instance = AirRiskInstance::new(map, 1).with_lateral_buffer(1.0, 1.0);
+        let corridor: Vec<Coord<i16>> = corridor_pixels(Coord{x: 1, y: 2}, Coord{x: 6, y: 2}, 1.0, 1.0);
+        let on_row_3 = corridor.iter().filter(|c| c.y == 3).count();
+
+        assert!(on_row_3 > 0);
+        assert_eq!(instance.compute_air_risk(&route), 10.0 * on_row_3 as f64 / corridor.len() as f64);
+    }
+
+    #[test]
+    fn test_lateral_buffer_outside_of_map() {
+        let instance = AirRiskInstance::new(Grid::new(3, 3, 1), 1).with_lateral_buffer(5.0, 1.0);
+        let route = path(vec![Coord{x: 0, y: 0}, Coord{x: 2, y: 0}]);
+
+        assert_eq!(instance.compute_air_risk(&route), 1.0);
+    }
+}
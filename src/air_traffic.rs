@@ -0,0 +1 @@
+pub mod air_traffic;
\ No newline at end of file
@@ -0,0 +1,311 @@
+#[cfg(test)]
+#[path = "tests.rs"]
+mod tests;
+
+use std::io::Read;
+use hashbrown::HashMap;
+use bresenham::Bresenham;
+use serde::{Serialize, Deserialize};
+use crate::risks::risks::Coord;
+use crate::grid::grid::Grid;
+use crate::air_risks::air_risks::{AirRiskInstance, AirRiskLayer, AltitudeBand};
+
+const EARTH_RADIUS_M: f64 = 6371000.0;
+
+// A position report of a manned aircraft, one row of an ADS-B recording.
+// Rows with missing values (e.g. no position fix) are skipped when reading.
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+pub struct TrackPoint {
+    #[serde(alias = "icao24", alias = "icao")]
+    pub id: String,
+    #[serde(alias = "time", alias = "timestamp")]
+    pub time_s: Option<f64>,
+    #[serde(alias = "latitude")]
+    pub lat: Option<f64>,
+    #[serde(alias = "longitude")]
+    pub lon: Option<f64>,
+    #[serde(alias = "altitude")]
+    pub alt_m: Option<f64>
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TimedPosition {
+    pub time_s: f64,
+    pub lat: f64,
+    pub lon: f64,
+    pub alt_m: f64
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Track {
+    pub id: String,
+    pub positions: Vec<TimedPosition>
+}
+
+// Reads a CSV recording with the columns id, time_s, lat, lon, alt_m (in any order)
+// and groups it into tracks ordered by time.
+pub fn read_tracks<R: Read>(reader: R) -> Result<Vec<Track>, csv::Error> {
+    let mut tracks: HashMap<String, Vec<TimedPosition>> = HashMap::new();
+
+    for row in csv::Reader::from_reader(reader).deserialize() {
+        let point: TrackPoint = row?;
+
+        if let (Some(time_s), Some(lat), Some(lon), Some(alt_m)) = (point.time_s, point.lat, point.lon, point.alt_m) {
+            tracks.entry(point.id).or_default().push(TimedPosition{ time_s, lat, lon, alt_m });
+        }
+    }
+
+    let mut res: Vec<Track> = tracks.into_iter()
+        .map(|(id, mut positions)| {
+            positions.sort_by(|a, b| a.time_s.total_cmp(&b.time_s));
+            Track{ id, positions }
+        })
+        .collect();
+    res.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Ok(res)
+}
+
+// Places the pixel grid of a map on the Earth: pixel (0, 0) is centred at (lat0, lon0),
+// x grows to the east and y to the south. Uses a local equirectangular projection which is
+// accurate enough for city-sized maps.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Georeference {
+    pub lat0: f64,
+    pub lon0: f64,
+    pub m_per_pixel: f64
+}
+
+impl Georeference {
+    pub fn pixel_of(&self, lat: f64, lon: f64) -> (f64, f64) {
+        let x_m = (lon - self.lon0).to_radians() * self.lat0.to_radians().cos() * EARTH_RADIUS_M;
+        let y_m = (self.lat0 - lat).to_radians() * EARTH_RADIUS_M;
+
+        (x_m / self.m_per_pixel, y_m / self.m_per_pixel)
+    }
+}
+
+// Splits every track segment into pieces of at most half a pixel and calls `visit(cell, time_s, dt_s, alt_m)`
+// with the middle of every piece. Segments with a gap longer than `max_gap_s` are not interpolated.
+pub fn rasterise_tracks<F: FnMut(Coord<i16>, f64, f64, f64)>(tracks: &[Track], georef: &Georeference, max_gap_s: f64, mut visit: F) {
+    for track in tracks {
+        for segment in track.positions.windows(2) {
+            if !is_gap(&segment[0], &segment[1], max_gap_s) {
+                rasterise_segment(&segment[0], &segment[1], georef, &mut visit);
+            }
+        }
+    }
+}
+
+// Contact with the aircraft was lost between the positions, or they are out of order
+fn is_gap(p0: &TimedPosition, p1: &TimedPosition, max_gap_s: f64) -> bool {
+    let dt = p1.time_s - p0.time_s;
+
+    dt <= 0.0 || dt > max_gap_s
+}
+
+fn rasterise_segment<F: FnMut(Coord<i16>, f64, f64, f64)>(p0: &TimedPosition, p1: &TimedPosition, georef: &Georeference, visit: &mut F) {
+    let dt = p1.time_s - p0.time_s;
+    let (x0, y0) = georef.pixel_of(p0.lat, p0.lon);
+    let (x1, y1) = georef.pixel_of(p1.lat, p1.lon);
+    let length_px = ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt();
+    let pieces = (length_px * 2.0).ceil().max(1.0) as usize;
+
+    for i in 0..pieces {
+        let f = (i as f64 + 0.5) / pieces as f64;
+        let cell = Coord{x: (x0 + (x1 - x0) * f).round() as i16, y: (y0 + (y1 - y0) * f).round() as i16};
+
+        visit(cell, p0.time_s + dt * f, dt / pieces as f64, p0.alt_m + (p1.alt_m - p0.alt_m) * f);
+    }
+}
+
+// Builds the air risk grid of a map with `rows` x `cols` pixels from recorded tracks.
+// Every cell counts how many times an aircraft within the altitude band entered it,
+// `total_time_s` is the duration of the recording.
+pub fn build_air_risk_map(tracks: &[Track], georef: &Georeference, rows: usize, cols: usize, band: &AltitudeBand, max_gap_s: f64) -> AirRiskInstance {
+    AirRiskInstance::new(rasterise_band(tracks, georef, rows, cols, band, max_gap_s), recording_time_s(tracks))
+}
+
+// Same as `build_air_risk_map`, with a separate layer for every altitude band
+pub fn build_layered_air_risk_map(tracks: &[Track], georef: &Georeference, rows: usize, cols: usize, bands: &[AltitudeBand], max_gap_s: f64) -> AirRiskInstance {
+    let layers = bands.iter()
+        .map(|band| AirRiskLayer{band: *band, map: rasterise_band(tracks, georef, rows, cols, band, max_gap_s)})
+        .collect();
+
+    AirRiskInstance::with_layers(layers, recording_time_s(tracks))
+}
+
+fn rasterise_band(tracks: &[Track], georef: &Georeference, rows: usize, cols: usize, band: &AltitudeBand, max_gap_s: f64) -> Grid<i32> {
+    let mut map = Grid::new(rows, cols, 0);
+
+    for track in tracks {
+        let mut previous_cell: Option<Coord<i16>> = None;
+
+        for segment in track.positions.windows(2) {
+            // An aircraft seen again after a gap enters the cell anew, even the one it was last seen in
+            if is_gap(&segment[0], &segment[1], max_gap_s) {
+                previous_cell = None;
+                continue;
+            }
+
+            rasterise_segment(&segment[0], &segment[1], georef, &mut |cell, _, _, alt_m| {
+                let inside = band.contains(alt_m) && cell.x >= 0 && cell.y >= 0 && (cell.y as usize) < rows && (cell.x as usize) < cols;
+
+                if inside && previous_cell != Some(cell) {
+                    map.set(cell.y as usize, cell.x as usize, map.at(cell) + 1);
+                }
+
+                previous_cell = if inside { Some(cell) } else { None };
+            });
+        }
+    }
+
+    map
+}
+
+fn recording_time_s(tracks: &[Track]) -> i32 {
+    let times = tracks.iter().flat_map(|t| t.positions.iter().map(|p| p.time_s));
+    let (first_s, last_s) = times.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), t| (lo.min(t), hi.max(t)));
+
+    if first_s < last_s { (last_s - first_s).round() as i32 } else { 0 }
+}
+
+// Aircraft-seconds spent in every cell, split into time bins of `bin_s` seconds.
+// With a `period_s` (e.g. 86400) the recording is folded into one period, so that a bin
+// describes e.g. the same hour of all the recorded days.
+pub struct SpaceTimeOccupancy {
+    pub start_s: f64,
+    pub bin_s: f64,
+    pub period_s: Option<f64>,
+    rows: usize,
+    cols: usize,
+    bins: Vec<HashMap<usize, f64>>,
+    coverage_s: Vec<f64>
+}
+
+impl SpaceTimeOccupancy {
+    // Panics unless the bins and the period are longer than 0 s
+    pub fn from_tracks(tracks: &[Track], georef: &Georeference, rows: usize, cols: usize, bin_s: f64, period_s: Option<f64>, max_gap_s: f64) -> Self {
+        assert!(bin_s > 0.0, "The time bins must be longer than 0 s, not {} s", bin_s);
+        assert!(period_s.is_none_or(|period| period > 0.0), "The period must be longer than 0 s, not {:?} s", period_s);
+
+        let times = tracks.iter().flat_map(|t| t.positions.iter().map(|p| p.time_s));
+        let (first_s, last_s) = times.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), t| (lo.min(t), hi.max(t)));
+
+        let (start_s, duration_s) = match period_s {
+            Some(period) => (0.0, period),
+            None if first_s <= last_s => (first_s, last_s - first_s),
+            None => (0.0, 0.0)
+        };
+
+        let bins_count = (duration_s / bin_s).ceil().max(1.0) as usize;
+
+        let mut res = Self{
+            start_s,
+            bin_s,
+            period_s,
+            rows,
+            cols,
+            bins: vec![HashMap::new(); bins_count],
+            coverage_s: vec![0.0; bins_count]
+        };
+
+        // How long each bin was observed, a bin can be covered several times when folding
+        let mut t = first_s;
+        while t < last_s {
+            let bin = res.bin_of(t).unwrap();
+            let offset = res.fold_offset(t);
+            let bin_end = match period_s {
+                Some(period) => (offset + (bin as f64 + 1.0) * bin_s).min(offset + period),
+                None => res.start_s + (bin as f64 + 1.0) * bin_s
+            };
+            let next = bin_end.min(last_s);
+
+            res.coverage_s[bin] += next - t;
+            t = if next > t { next } else { t + bin_s };
+        }
+
+        rasterise_tracks(tracks, georef, max_gap_s, |cell, time_s, dt, _| {
+            if let (Some(bin), Some(index)) = (res.bin_of(time_s), res.cell_index(cell)) {
+                *res.bins[bin].entry(index).or_insert(0.0) += dt;
+            }
+        });
+
+        res
+    }
+
+    pub fn bins_count(&self) -> usize {
+        self.bins.len()
+    }
+
+    // Mean number of aircraft in the cell during the time bin containing `time_s`
+    pub fn mean_aircraft_at(&self, cell: Coord<i16>, time_s: f64) -> f64 {
+        match (self.bin_of(time_s), self.cell_index(cell)) {
+            (Some(bin), Some(index)) if self.coverage_s[bin] > 0.0 => {
+                self.bins[bin].get(&index).copied().unwrap_or(0.0) / self.coverage_s[bin]
+            },
+            _ => 0.0
+        }
+    }
+
+    // Mean number of aircraft per pixel along the route, flown from its first waypoint at `departure_s`
+    // with a constant ground speed. The time-dependent counterpart of `AirRiskInstance::compute_air_risk`.
+    pub fn route_air_risk(&self, waypoints: &[Coord<i16>], departure_s: f64, speed_mps: f64, m_per_pixel: f64) -> f64 {
+        let mut air_risk = 0.0;
+        let mut length_px = 0;
+        let mut flown_m = 0.0;
+
+        for leg in waypoints.windows(2) {
+            let (s, e) = (leg[0], leg[1]);
+            let pixels: Vec<(isize, isize)> = Bresenham::new((s.x as isize, s.y as isize), (e.x as isize, e.y as isize)).collect();
+            let leg_m = (((e.x - s.x) as f64).powi(2) + ((e.y - s.y) as f64).powi(2)).sqrt() * m_per_pixel;
+            let step_m = leg_m / pixels.len().max(1) as f64;
+
+            for (x, y) in pixels {
+                let time_s = departure_s + flown_m / speed_mps;
+
+                air_risk += self.mean_aircraft_at(Coord{x: x as i16, y: y as i16}, time_s);
+                length_px += 1;
+                flown_m += step_m;
+            }
+        }
+
+        if length_px == 0 {
+            return 0.0
+        }
+
+        air_risk / length_px as f64
+    }
+
+    fn fold_offset(&self, time_s: f64) -> f64 {
+        match self.period_s {
+            Some(period) => (time_s / period).floor() * period,
+            None => 0.0
+        }
+    }
+
+    fn bin_of(&self, time_s: f64) -> Option<usize> {
+        let t = time_s - self.fold_offset(time_s) - self.start_s;
+
+        if t < 0.0 {
+            return None
+        }
+
+        let bin = (t / self.bin_s).floor() as usize;
+
+        // The end of a non-periodic recording belongs to the last bin
+        if bin == self.bins.len() && self.period_s.is_none() && t <= self.bins.len() as f64 * self.bin_s {
+            return Some(bin - 1)
+        }
+
+        if bin < self.bins.len() { Some(bin) } else { None }
+    }
+
+    fn cell_index(&self, cell: Coord<i16>) -> Option<usize> {
+        if cell.x < 0 || cell.y < 0 || cell.x as usize >= self.cols || cell.y as usize >= self.rows {
+            return None
+        }
+
+        Some(cell.y as usize * self.cols + cell.x as usize)
+    }
+}
@@ -0,0 +1,160 @@
+#[cfg(test)]
+mod tests {
+    use super::super::{*};
+
+    const GEOREF: Georeference = Georeference{lat0: 59.0, lon0: 18.0, m_per_pixel: 10.0};
+
+    fn position(x: f64, y: f64, time_s: f64, alt_m: f64) -> TimedPosition {
+        let lat = GEOREF.lat0 - (y * GEOREF.m_per_pixel / EARTH_RADIUS_M).to_degrees();
+        let lon = GEOREF.lon0 + (x * GEOREF.m_per_pixel / (EARTH_RADIUS_M * GEOREF.lat0.to_radians().cos())).to_degrees();
+
+        TimedPosition{time_s, lat, lon, alt_m}
+    }
+
+    // An aircraft flying east along row 0 at one pixel per second
+    fn eastbound_track() -> Track {
+        Track{id: "a".to_string(), positions: vec![position(0.0, 0.0, 0.0, 100.0), position(10.0, 0.0, 10.0, 100.0)]}
+    }
+
+    #[test]
+    fn test_georeference() {
+        let (x, y) = GEOREF.pixel_of(59.0, 18.0);
+        assert!(x.abs() < 1e-9 && y.abs() < 1e-9);
+
+        let p = position(3.0, -2.0, 0.0, 0.0);
+        let (x, y) = GEOREF.pixel_of(p.lat, p.lon);
+        assert!((x - 3.0).abs() < 1e-6 && (y + 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_read_tracks() {
+        let csv = "icao24,time,lat,lon,altitude\n\
+                   b,20,59.1,18.1,300\n\
+                   a,10,59.0,18.0,100\n\
+                   a,5,59.0,18.0,\n\
+                   b,10,59.2,18.2,200\n";
+
+        let tracks = read_tracks(csv.as_bytes()).unwrap();
+
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].id, "a");
+        assert_eq!(tracks[0].positions.len(), 1);
+        assert_eq!(tracks[1].positions.iter().map(|p| p.time_s).collect::<Vec<f64>>(), vec![10.0, 20.0]);
+    }
+
+    #[test]
+    fn test_rasterise_tracks() {
+        let mut total_time = 0.0;
+        let mut cells: Vec<Coord<i16>> = vec![];
+
+        rasterise_tracks(&[eastbound_track()], &GEOREF, 60.0, |cell, _, dt, alt_m| {
+            total_time += dt;
+            cells.push(cell);
+            assert!((alt_m - 100.0).abs() < 1e-9);
+        });
+
+        assert!((total_time - 10.0).abs() < 1e-9);
+        assert!(cells.iter().all(|c| c.y == 0 && 0 <= c.x && c.x <= 10));
+
+        let mut visited = false;
+        rasterise_tracks(&[eastbound_track()], &GEOREF, 5.0, |_, _, _, _| visited = true);
+        assert!(!visited);
+    }
+
+    #[test]
+    fn test_occupancy() {
+        let occupancy = SpaceTimeOccupancy::from_tracks(&[eastbound_track()], &GEOREF, 5, 20, 5.0, None, 60.0);
+
+        assert_eq!(occupancy.bins_count(), 2);
+        // One second spent in the cell during a 5 second bin
+        assert!((occupancy.mean_aircraft_at(Coord{x: 2, y: 0}, 1.0) - 0.2).abs() < 1e-9);
+        assert_eq!(occupancy.mean_aircraft_at(Coord{x: 2, y: 0}, 6.0), 0.0);
+        assert!((occupancy.mean_aircraft_at(Coord{x: 7, y: 0}, 6.0) - 0.2).abs() < 1e-9);
+        assert_eq!(occupancy.mean_aircraft_at(Coord{x: 2, y: 1}, 1.0), 0.0);
+        assert_eq!(occupancy.mean_aircraft_at(Coord{x: 2, y: 0}, 100.0), 0.0);
+    }
+
+    #[test]
+    fn test_periodic_occupancy() {
+        let day = 86400.0;
+        let first_day = Track{id: "a".to_string(), positions: vec![position(0.0, 0.0, 3600.0, 100.0), position(10.0, 0.0, 3610.0, 100.0)]};
+        // Positions outside of the map only to make the recording last two days
+        let span = Track{id: "span".to_string(), positions: vec![position(-100.0, -100.0, 0.0, 100.0), position(-100.0, -100.0, 2.0 * day, 100.0)]};
+
+        let occupancy = SpaceTimeOccupancy::from_tracks(&[first_day, span], &GEOREF, 5, 20, 3600.0, Some(day), 60.0);
+
+        assert_eq!(occupancy.bins_count(), 24);
+        let expected = 1.0 / (2.0 * 3600.0);
+        assert!((occupancy.mean_aircraft_at(Coord{x: 2, y: 0}, 3700.0) - expected).abs() < 1e-12);
+        assert!((occupancy.mean_aircraft_at(Coord{x: 2, y: 0}, day + 3700.0) - expected).abs() < 1e-12);
+        assert_eq!(occupancy.mean_aircraft_at(Coord{x: 2, y: 0}, 7300.0), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "time bins must be longer than 0 s")]
+    fn test_occupancy_without_time_bins() {
+        SpaceTimeOccupancy::from_tracks(&[eastbound_track()], &GEOREF, 5, 20, 0.0, None, 60.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "time bins must be longer than 0 s")]
+    fn test_occupancy_with_negative_time_bins() {
+        SpaceTimeOccupancy::from_tracks(&[eastbound_track()], &GEOREF, 5, 20, -5.0, Some(86400.0), 60.0);
+    }
+
+    #[test]
+    fn test_route_air_risk_depends_on_departure() {
+        let occupancy = SpaceTimeOccupancy::from_tracks(&[eastbound_track()], &GEOREF, 5, 20, 5.0, None, 60.0);
+        let route = vec![Coord{x: 0, y: 0}, Coord{x: 4, y: 0}];
+
+        let early = occupancy.route_air_risk(&route, 0.0, 10.0, GEOREF.m_per_pixel);
+        let late = occupancy.route_air_risk(&route, 6.0, 10.0, GEOREF.m_per_pixel);
+
+        assert!(early > 0.0);
+        assert_eq!(late, 0.0);
+    }
+
+    #[test]
+    fn test_build_air_risk_map() {
+        let climbing = Track{id: "b".to_string(), positions: vec![position(0.0, 2.0, 100.0, 100.0), position(10.0, 2.0, 110.0, 1100.0)]};
+        // Flies back and forth over the same cells
+        let returning = Track{id: "c".to_string(), positions: vec![position(0.0, 4.0, 0.0, 100.0), position(4.0, 4.0, 4.0, 100.0), position(0.0, 4.0, 8.0, 100.0)]};
+
+        let instance = build_air_risk_map(&[eastbound_track(), climbing, returning], &GEOREF, 5, 20, &AltitudeBand{min_m: 0.0, max_m: 500.0}, 60.0);
+
+        assert_eq!(instance.total_time_s, 110);
+        assert_eq!(instance.map.row(0)[..11], [1; 11]);
+        // The climbing aircraft leaves the band 4 pixels after the start
+        assert_eq!(instance.map.row(2)[..6], [1, 1, 1, 1, 1, 0]);
+        // Only the turning point is not entered twice
+        assert_eq!(instance.map.row(4)[..6], [2, 2, 2, 2, 1, 0]);
+        assert_eq!(instance.map.row(1).iter().sum::<i32>(), 0);
+    }
+
+    #[test]
+    fn test_build_air_risk_map_after_gap() {
+        // Contact is lost for 100 s over pixel 3, where the aircraft is seen again
+        let track = Track{id: "d".to_string(), positions: vec![
+            position(0.0, 0.0, 0.0, 100.0), position(3.0, 0.0, 3.0, 100.0),
+            position(3.0, 0.0, 103.0, 100.0), position(6.0, 0.0, 106.0, 100.0)
+        ]};
+
+        let instance = build_air_risk_map(&[track], &GEOREF, 1, 8, &AltitudeBand{min_m: 0.0, max_m: 500.0}, 60.0);
+
+        assert_eq!(instance.map.row(0), &[1, 1, 1, 2, 1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn test_build_layered_air_risk_map() {
+        let low = Track{id: "low".to_string(), positions: vec![position(0.0, 0.0, 0.0, 50.0), position(4.0, 0.0, 4.0, 50.0)]};
+        let high = Track{id: "high".to_string(), positions: vec![position(0.0, 1.0, 0.0, 150.0), position(4.0, 1.0, 4.0, 150.0)]};
+        let bands = [AltitudeBand{min_m: 0.0, max_m: 100.0}, AltitudeBand{min_m: 100.0, max_m: 200.0}];
+
+        let instance = build_layered_air_risk_map(&[low, high], &GEOREF, 2, 6, &bands, 60.0);
+
+        assert_eq!(instance.layers.len(), 2);
+        assert_eq!(instance.layers[0].map.to_rows(), vec![vec![1, 1, 1, 1, 1, 0], vec![0; 6]]);
+        assert_eq!(instance.layers[1].map.to_rows(), vec![vec![0; 6], vec![1, 1, 1, 1, 1, 0]]);
+        assert_eq!(instance.map.to_rows(), vec![vec![1, 1, 1, 1, 1, 0], vec![1, 1, 1, 1, 1, 0]]);
+    }
+}
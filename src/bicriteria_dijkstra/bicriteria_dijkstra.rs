@@ -2,26 +2,61 @@
 #[path = "tests.rs"]
 mod tests;
 
-use crate::risks::risks::{RiskMap, Coord};
+use crate::risks::risks::{RiskMap, Coord, Neighbourhood, NeighboursIter, gcd};
+use crate::air_risks::air_risks::{AirRiskInstance, AltitudeProfile};
+use serde::{Serialize, Deserialize};
 use priority_queue::PriorityQueue;
-use hashbrown::{HashMap, HashSet};
+use hashbrown::HashMap;
 use hashbrown::hash_map::DefaultHashBuilder;
-use hashbrown::hash_map::Entry::{Occupied, Vacant};
 use std::cmp::Reverse;
 use std::fmt::{Display, Formatter};
 use ordered_float::OrderedFloat;
+use crate::grid::grid::Grid;
+use crate::waypoint_graph::waypoint_graph::WaypointGraph;
+use crate::search_backend::search_backend::{LabelStorage, QueueKind, Labels, HashLabels, DenseLabels, SearchQueue, BinaryQueue, RadixHeap};
 
 
+// A cell with the direction of the edge it was reached by, reduced by the gcd of its coordinates.
+// The origin has the direction (0, 0).
+type HeadingState = (Coord<i16>, Coord<i16>);
+
+// A neighbour with the ground risk and the length of the edge to it
+type CriteriaEdge = (Coord<i16>, i32, f64);
+
+// The lower bound of the objective of a route in `search_constrained`, then its bounded criterion
+type ConstrainedPriority = Reverse<(OrderedFloat<f64>, OrderedFloat<f64>)>;
+
 struct BicriteriaDijkstra <'a> {
     risk_map: &'a RiskMap,
 }
 
+#[derive(Clone)]
 pub struct BicriteriaDijkstraInstance <'a> {
     pub risk_map: &'a RiskMap,
     pub from: Coord<i16>,
     pub to: Coord<i16>,
     pub search_limit: i16,
-    pub r_m: f64
+    pub r_m: f64,
+    pub turn_constraint: Option<TurnConstraint>,
+    pub neighbourhood: Neighbourhood,
+    pub waypoint_graph: Option<&'a WaypointGraph>,
+    // Only the cells marked in the corridor are searched
+    pub corridor: Option<&'a Grid<bool>>,
+    pub bidirectional: bool,
+    // Cells which cannot lead to a route lighter than the neighbouring hull routes are not searched
+    pub hull_bounds: bool,
+    pub label_storage: LabelStorage,
+    pub queue: QueueKind,
+    // Every search prints its alpha
+    pub progress: bool
+}
+
+// Limits the heading change between consecutive legs, e.g. for fixed-wing aircraft
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TurnConstraint {
+    pub max_turn_deg: f64,
+    // Added to the weight of an edge for every radian of heading change, in metres
+    pub penalty_m_per_rad: f64
 }
 
 #[derive(Debug)]
@@ -30,7 +65,114 @@ pub struct Path {
     pub linear_combination_weight: f64,
     pub risk: i32,
     pub length_m: f64,
-    pub alpha: f64
+    pub alpha: f64,
+    pub altitude_profile: Option<AltitudeProfile>
+}
+
+// One straight leg of a route, in the order of flight. The cumulative values cover the route from
+// its origin up to the end of this leg; the cumulative air risk is the air risk of that part of the
+// route, like `AirRiskInstance::compute_air_risk` it is a mean and not a sum. The first leg also holds
+// the footprint of the origin, which `Path::risk` counts as a leg of zero length.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Leg {
+    pub from: Coord<i16>,
+    pub to: Coord<i16>,
+    pub length_m: f64,
+    pub ground_risk: i32,
+    pub air_risk: f64,
+    pub cumulative_length_m: f64,
+    pub cumulative_ground_risk: i32,
+    pub cumulative_air_risk: f64
+}
+
+// A bound on one criterion of a route, the other criterion is minimised
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RouteConstraint {
+    // The least risky route not longer than the given length in metres, e.g. the battery range
+    MaxLength(f64),
+    // The shortest route with at most the given ground risk
+    MaxRisk(i32)
+}
+
+impl RouteConstraint {
+    // The criterion which is minimised
+    fn objective(&self, risk: i32, length_m: f64) -> f64 {
+        match self {
+            RouteConstraint::MaxLength(_) => risk as f64,
+            RouteConstraint::MaxRisk(_) => length_m
+        }
+    }
+
+    // The lower bound of the objective from the lower bounds of the two criteria
+    fn objective_bound(&self, least_risk: f64, least_length_m: f64) -> f64 {
+        match self {
+            RouteConstraint::MaxLength(_) => least_risk,
+            RouteConstraint::MaxRisk(_) => least_length_m
+        }
+    }
+
+    // The bounded criterion
+    fn resource(&self, risk: i32, length_m: f64) -> f64 {
+        match self {
+            RouteConstraint::MaxLength(_) => length_m,
+            RouteConstraint::MaxRisk(_) => risk as f64
+        }
+    }
+
+    fn allows(&self, risk: f64, length_m: f64) -> bool {
+        match *self {
+            RouteConstraint::MaxLength(max_length_m) => length_m <= max_length_m,
+            RouteConstraint::MaxRisk(max_risk) => risk <= max_risk as f64
+        }
+    }
+}
+
+// A route of `search_constrained` to a cell, extending the label `previous`. It is dominated once another
+// route to the same state is not riskier and not longer.
+struct ConstrainedLabel {
+    state: HeadingState,
+    risk: i32,
+    length_m: f64,
+    previous: Option<usize>,
+    dominated: bool
+}
+
+// None of the routes satisfies the constraint, `closest` is the shortest length or the lowest risk among them
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct NoFeasibleRoute {
+    pub constraint: RouteConstraint,
+    pub closest: f64
+}
+
+impl Display for NoFeasibleRoute {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.constraint {
+            RouteConstraint::MaxLength(max_length_m) => write!(f, "No route is at most {} m long, the shortest one has {} m", max_length_m, self.closest),
+            RouteConstraint::MaxRisk(max_risk) => write!(f, "No route has a risk of at most {}, the safest one has {}", max_risk, self.closest)
+        }
+    }
+}
+
+impl std::error::Error for NoFeasibleRoute {}
+
+// The best of the routes satisfying the constraint
+pub fn best_feasible_path(paths: Vec<Path>, constraint: RouteConstraint) -> Result<Path, NoFeasibleRoute> {
+    let closest = match constraint {
+        RouteConstraint::MaxLength(_) => paths.iter().map(|p| p.length_m).fold(f64::INFINITY, f64::min),
+        RouteConstraint::MaxRisk(_) => paths.iter().map(|p| p.risk as f64).fold(f64::INFINITY, f64::min)
+    };
+
+    let feasible = paths.into_iter().filter(|p| match constraint {
+        RouteConstraint::MaxLength(max_length_m) => p.length_m <= max_length_m,
+        RouteConstraint::MaxRisk(max_risk) => p.risk <= max_risk
+    });
+
+    let best = match constraint {
+        RouteConstraint::MaxLength(_) => feasible.min_by(|a, b| a.risk.cmp(&b.risk).then(a.length_m.total_cmp(&b.length_m))),
+        RouteConstraint::MaxRisk(_) => feasible.min_by(|a, b| a.length_m.total_cmp(&b.length_m).then(a.risk.cmp(&b.risk)))
+    };
+
+    best.ok_or(NoFeasibleRoute{ constraint, closest })
 }
 
 impl <'a> BicriteriaDijkstraInstance <'a> {
@@ -40,10 +182,93 @@ impl <'a> BicriteriaDijkstraInstance <'a> {
             from,
             to,
             search_limit,
-            r_m: r
+            r_m: r,
+            turn_constraint: None,
+            neighbourhood: Neighbourhood::SQUARE,
+            waypoint_graph: None,
+            corridor: None,
+            bidirectional: false,
+            hull_bounds: false,
+            label_storage: LabelStorage::Dense,
+            queue: QueueKind::Binary,
+            progress: false
         }
     }
 
+    // Searches on the waypoint graph instead of the grid, `from` and `to` have to be waypoints of it
+    pub fn with_waypoint_graph(mut self, graph: &'a WaypointGraph) -> Self {
+        self.waypoint_graph = Some(graph);
+        self
+    }
+
+    // Searches with only some of the cells within `search_limit` as neighbours
+    pub fn with_neighbourhood(mut self, neighbourhood: Neighbourhood) -> Self {
+        self.neighbourhood = neighbourhood;
+        self
+    }
+
+    // Routes of `run_with_alpha` are searched from both ends at once, which explores about half as many
+    // cells on long routes. Ignored with a turn constraint.
+    pub fn with_bidirectional_search(mut self) -> Self {
+        self.bidirectional = true;
+        self
+    }
+
+    // Every search of `compute_pareto_apx_paths` is bounded by the weights of the two routes it is searched
+    // between: the new route is at most as heavy as either of them, so a cell whose label plus the straight
+    // distance to the destination exceeds both can be skipped.
+    pub fn with_hull_bounds(mut self) -> Self {
+        self.hull_bounds = true;
+        self
+    }
+
+    // Prints the alpha of every search, a whole front takes minutes on a large map
+    pub fn with_progress(mut self) -> Self {
+        self.progress = true;
+        self
+    }
+
+    // The data structures of the one-directional search without a turn constraint
+    pub fn with_search_backend(mut self, label_storage: LabelStorage, queue: QueueKind) -> Self {
+        self.label_storage = label_storage;
+        self.queue = queue;
+        self
+    }
+
+    fn neighbours(&self, node: Coord<i16>) -> NeighboursIter<'a> {
+        self.risk_map.neighbours_in(node, self.search_limit, self.neighbourhood)
+    }
+
+    // The neighbours of the node with the weights of the edges to them
+    fn edges_from(&self, node: Coord<i16>, alpha: f64) -> Vec<(Coord<i16>, f64)> {
+        match self.waypoint_graph {
+            Some(graph) => graph.edges_from(node).iter().map(|edge| (edge.to, edge.risk as f64 * alpha + edge.length_m)).collect(),
+            None => self.neighbours(node)
+                .filter(|neighbour| self.corridor.is_none_or(|corridor| corridor.at(*neighbour)))
+                .filter_map(|neighbour| self.edge_weight(node, neighbour, alpha).map(|weight| (neighbour, weight)))
+                .collect()
+        }
+    }
+
+    // The neighbours of the node with the weights of the edges from them to the node, for searching backwards
+    fn edges_to(&self, node: Coord<i16>, alpha: f64) -> Vec<(Coord<i16>, f64)> {
+        match self.waypoint_graph {
+            // The edges of the waypoint graph go both ways
+            Some(_) => self.edges_from(node, alpha),
+            None => self.neighbours(node)
+                .filter(|neighbour| *neighbour == self.from || self.corridor.is_none_or(|corridor| corridor.at(*neighbour)))
+                .filter_map(|neighbour| self.edge_weight(neighbour, node, alpha).map(|weight| (neighbour, weight)))
+                .collect()
+        }
+    }
+
+    // Routes of `run_with_alpha` are searched over (cell, heading) states so that no turn is sharper than
+    // `max_turn_deg`. The one-to-all labels are not affected.
+    pub fn with_turn_constraint(mut self, max_turn_deg: f64, penalty_m_per_rad: f64) -> Self {
+        self.turn_constraint = Some(TurnConstraint{ max_turn_deg, penalty_m_per_rad });
+        self
+    }
+
     pub fn compute_pareto_apx_paths(&self) -> Vec<Path> {
         let mut paths: Vec<Path> = vec![];
 
@@ -63,7 +288,13 @@ impl <'a> BicriteriaDijkstraInstance <'a> {
             let beta = (path1.risk-path0.risk) as f64/(path1.length_m-path0.length_m);
 
             if beta < -0.0000001 {
-                let new_path = self.run_with_alpha(-1.0/beta);
+                let alpha = -1.0/beta;
+                let new_path = if self.hull_bounds {
+                    let upper_bound = self.route_weight(path0, alpha).min(self.route_weight(path1, alpha));
+                    self.run(alpha, upper_bound).unwrap_or_else(|| self.unreachable())
+                } else {
+                    self.run_with_alpha(alpha)
+                };
 
                 if interval.1 - interval.0 != 1 {
                     panic!("An error with intervals!");
@@ -82,80 +313,571 @@ impl <'a> BicriteriaDijkstraInstance <'a> {
         return paths
     }
 
-    pub fn run_with_alpha(&self, alpha: f64) -> Path {
-        println!("Computing for alpha={}", alpha);
-        let mut labels: HashMap<Coord<i16>, f64> = HashMap::new();
-        let mut previous_nodes: HashMap<Coord<i16>, Coord<i16>> = HashMap::new();
-        let mut pq: PriorityQueue<_, Reverse<OrderedFloat<f64>>, DefaultHashBuilder> = PriorityQueue::<_, Reverse<OrderedFloat<f64>>, DefaultHashBuilder>::with_default_hasher();
+    // The best route satisfying the constraint. The best feasible route of `compute_pareto_apx_paths` is only
+    // the best one on the convex hull of the Pareto front, the label-setting search then looks for a better
+    // route among all of them. With a turn constraint the turns are kept, their penalty is not counted.
+    pub fn compute_constrained_path(&self, constraint: RouteConstraint) -> Result<Path, NoFeasibleRoute> {
+        let hull_path = best_feasible_path(self.compute_pareto_apx_paths(), constraint);
+        let upper_bound = hull_path.as_ref().map_or(f64::INFINITY, |path| constraint.objective(path.risk, path.length_m));
+
+        match self.search_constrained(constraint, upper_bound) {
+            Some(path) => Ok(path),
+            None => hull_path
+        }
+    }
 
-        pq.push(self.from, Reverse(OrderedFloat(0.0)));
-        labels.insert(self.from, 0.0);
-        previous_nodes.insert(self.from, self.from);
+    // Label-setting search over the risk and the length of the routes, every cell keeps the routes to it that
+    // no other route to it beats in both. Routes which cannot stay within the constraint or end lighter than
+    // `upper_bound` are dropped, the lower bounds to the destination being the straight length and the least risk.
+    fn search_constrained(&self, constraint: RouteConstraint, upper_bound: f64) -> Option<Path> {
+        if self.progress {
+            println!("Searching for the best route with {:?}", constraint);
+        }
 
-        while !pq.is_empty() {
-            let current_node = pq.pop();
-            let current_node = current_node.unwrap().0;
-            let current_label = *labels.get(&current_node).unwrap();
+        let least_risks = self.least_risks_to_destination();
+        let bounds = |node: Coord<i16>| (least_risks.at(node), self.risk_map.length_m(node, self.to));
+        let start: HeadingState = (self.from, Coord{x: 0, y: 0});
+
+        let mut labels = vec![ConstrainedLabel{
+            state: start,
+            risk: self.risk_map.risk(self.from, self.from, self.r_m),
+            length_m: self.risk_map.length_m(self.from, self.from),
+            previous: None,
+            dominated: false
+        }];
+        let mut labels_at: HashMap<HeadingState, Vec<usize>> = HashMap::new();
+        let mut edges: HashMap<Coord<i16>, Vec<CriteriaEdge>> = HashMap::new();
+        let mut pq: PriorityQueue<usize, ConstrainedPriority, DefaultHashBuilder> = PriorityQueue::with_default_hasher();
+
+        labels_at.insert(start, vec![0]);
+        pq.push(0, Reverse((OrderedFloat(0.0), OrderedFloat(0.0))));
+
+        while let Some((index, _)) = pq.pop() {
+            let ConstrainedLabel{ state: (current_node, heading), risk, length_m, dominated, .. } = labels[index];
+
+            if dominated {
+                continue;
+            }
 
+            // The lower bounds never overestimate, so no later route can be lighter
             if current_node == self.to {
+                return Some(self.constrained_path(&labels, index));
+            }
+
+            let node_edges = edges.entry(current_node).or_insert_with(|| self.criteria_edges(current_node, false));
+
+            for &(neighbour, edge_risk, edge_length_m) in node_edges.iter() {
+                let direction = match self.turn_constraint {
+                    Some(turns) => {
+                        let direction = primitive_direction(Coord{x: neighbour.x - current_node.x, y: neighbour.y - current_node.y});
+                        if turn_angle(heading, direction) > turns.max_turn_deg.to_radians() + 1e-9 {
+                            continue;
+                        }
+                        direction
+                    },
+                    None => Coord{x: 0, y: 0}
+                };
+
+                let (new_risk, new_length_m) = (risk + edge_risk, length_m + edge_length_m);
+                let (least_risk, least_length_m) = bounds(neighbour);
+
+                if !constraint.allows(new_risk as f64 + least_risk, new_length_m + least_length_m) {
+                    continue;
+                }
+
+                let objective = constraint.objective(new_risk, new_length_m) + constraint.objective_bound(least_risk, least_length_m);
+                if objective > upper_bound * (1.0 + 1e-9) + 1e-6 {
+                    continue;
+                }
+
+                let next: HeadingState = (neighbour, direction);
+                let others = labels_at.entry(next).or_default();
+
+                if others.iter().any(|&other| labels[other].risk <= new_risk && labels[other].length_m <= new_length_m) {
+                    continue;
+                }
+
+                others.retain(|&other| {
+                    let beaten = new_risk <= labels[other].risk && new_length_m <= labels[other].length_m;
+                    labels[other].dominated |= beaten;
+                    !beaten
+                });
+                others.push(labels.len());
+
+                pq.push(labels.len(), Reverse((OrderedFloat(objective), OrderedFloat(constraint.resource(new_risk, new_length_m)))));
+                labels.push(ConstrainedLabel{ state: next, risk: new_risk, length_m: new_length_m, previous: Some(index), dominated: false });
+            }
+        }
+
+        None
+    }
+
+    // The route of the label from the destination back to the origin, with the weight of alpha 0
+    fn constrained_path(&self, labels: &[ConstrainedLabel], index: usize) -> Path {
+        let mut path = vec![labels[index].state.0];
+        let mut previous = labels[index].previous;
+
+        while let Some(i) = previous {
+            path.push(labels[i].state.0);
+            previous = labels[i].previous;
+        }
+
+        let mut res = self.path_of_nodes(path, 0.0, 0.0);
+        res.linear_combination_weight = res.length_m;
+
+        res
+    }
+
+    // The least ground risk of a route from every cell to the destination, infinite where the destination
+    // cannot be reached. Turns are not constrained, so it is a lower bound with a turn constraint.
+    fn least_risks_to_destination(&self) -> Grid<f64> {
+        let mut risks = Grid::new(self.risk_map.map.rows(), self.risk_map.map.cols(), f64::INFINITY);
+        let mut pq: PriorityQueue<Coord<i16>, Reverse<i32>, DefaultHashBuilder> = PriorityQueue::with_default_hasher();
+
+        risks.set(self.to.y as usize, self.to.x as usize, 0.0);
+        pq.push(self.to, Reverse(0));
+
+        while let Some((current_node, Reverse(risk))) = pq.pop() {
+            for (neighbour, edge_risk, _) in self.criteria_edges(current_node, true) {
+                let new_risk = risk + edge_risk;
+
+                if risks.at(neighbour) > new_risk as f64 {
+                    risks.set(neighbour.y as usize, neighbour.x as usize, new_risk as f64);
+                    pq.push(neighbour, Reverse(new_risk));
+                }
+            }
+        }
+
+        risks
+    }
+
+    // The neighbours of the node with the ground risk and the length of the edges to them, or from them
+    // when searching backwards, like `edges_from` and `edges_to`
+    fn criteria_edges(&self, node: Coord<i16>, backwards: bool) -> Vec<CriteriaEdge> {
+        match self.waypoint_graph {
+            Some(graph) => graph.edges_from(node).iter().map(|edge| (edge.to, edge.risk, edge.length_m)).collect(),
+            None => self.neighbours(node)
+                .filter(|neighbour| (backwards && *neighbour == self.from) || self.corridor.is_none_or(|corridor| corridor.at(*neighbour)))
+                .map(|neighbour| if backwards { (neighbour, neighbour, node) } else { (neighbour, node, neighbour) })
+                .filter(|(_, p1, p2)| self.risk_map.blocking_zone(*p1, *p2, self.r_m).is_none())
+                .map(|(neighbour, p1, p2)| (neighbour, self.risk_map.risk(p1, p2, self.r_m), self.risk_map.length_m(p1, p2)))
+                .collect()
+        }
+    }
+
+    pub fn run_with_alpha(&self, alpha: f64) -> Path {
+        self.try_run_with_alpha(alpha).unwrap_or_else(|| self.unreachable())
+    }
+
+    // The route of `run_with_alpha`, `None` if the destination cannot be reached, e.g. within a corridor
+    pub fn try_run_with_alpha(&self, alpha: f64) -> Option<Path> {
+        self.run(alpha, f64::INFINITY)
+    }
+
+    fn unreachable(&self) -> ! {
+        match self.turn_constraint {
+            Some(_) => panic!("The destination cannot be reached, it may be closed off by no-fly zones or the turn constraint"),
+            None => panic!("The destination cannot be reached, it may be closed off by no-fly zones")
+        }
+    }
+
+    // Cells whose label plus the straight distance to the destination exceeds `upper_bound` are skipped,
+    // there has to be a route not heavier than the bound
+    fn run(&self, alpha: f64, upper_bound: f64) -> Option<Path> {
+        if self.progress {
+            println!("Computing for alpha={}", alpha);
+        }
+
+        if let Some(turns) = &self.turn_constraint {
+            return self.search_with_turns(alpha, turns, upper_bound);
+        }
+
+        if self.bidirectional {
+            return self.search_bidirectional(alpha, upper_bound);
+        }
+
+        let labels = self.search(alpha, Some(self.to), upper_bound);
+
+        self.unwrap_path(labels.as_ref(), alpha)
+    }
+
+    // Labels of all the cells reachable from `from`, on a grid of the same shape as the risk map.
+    // Unreachable cells get an infinite label.
+    pub fn one_to_all_labels(&self, alpha: f64) -> Grid<f64> {
+        self.search(alpha, None, f64::INFINITY).to_grid(self.risk_map.map.rows(), self.risk_map.map.cols())
+    }
+
+    fn search(&self, alpha: f64, target: Option<Coord<i16>>, upper_bound: f64) -> Box<dyn Labels> {
+        let (rows, cols) = (self.risk_map.map.rows(), self.risk_map.map.cols());
+
+        match (self.label_storage, self.queue) {
+            (LabelStorage::Hash, QueueKind::Binary) => Box::new(self.search_with(HashLabels::new(), BinaryQueue::new(), alpha, target, upper_bound)),
+            (LabelStorage::Hash, QueueKind::Radix) => Box::new(self.search_with(HashLabels::new(), RadixHeap::new(), alpha, target, upper_bound)),
+            (LabelStorage::Dense, QueueKind::Binary) => Box::new(self.search_with(DenseLabels::new(rows, cols), BinaryQueue::new(), alpha, target, upper_bound)),
+            (LabelStorage::Dense, QueueKind::Radix) => Box::new(self.search_with(DenseLabels::new(rows, cols), RadixHeap::new(), alpha, target, upper_bound))
+        }
+    }
+
+    fn search_with<L: Labels, Q: SearchQueue>(&self, mut labels: L, mut pq: Q, alpha: f64, target: Option<Coord<i16>>, upper_bound: f64) -> L {
+        pq.push(self.from, 0.0);
+        labels.set(self.from, 0.0, self.from);
+
+        while let Some((current_node, label)) = pq.pop() {
+            let current_label = labels.label(current_node);
+
+            // An outdated entry, the cell was popped with its current label already
+            if label > current_label {
+                continue;
+            }
+
+            if Some(current_node) == target {
                 break;
             }
 
-            for neighbour in self.risk_map.neighbours_within(current_node, self.search_limit) {
-                let weight = self.risk_map.risk(current_node, neighbour, self.r_m) as f64 * alpha + self.risk_map.length_m(current_node, neighbour);
+            for (neighbour, weight) in self.edges_from(current_node, alpha) {
                 let new_label = current_label + weight;
 
-                let mut entry = labels.entry(neighbour);
+                if labels.label(neighbour) > new_label && !self.exceeds_bound(new_label, neighbour, self.to, upper_bound) {
+                    labels.set(neighbour, new_label, current_node);
+                    pq.push(neighbour, new_label);
+                }
+            }
+        }
+
+        labels
+    }
 
-                match entry {
-                    Occupied(mut entry) => {
-                        if entry.get() > &new_label {
-                            entry.insert(new_label);
-                            *previous_nodes.entry(neighbour).or_insert(current_node) = current_node;
-                            pq.push(neighbour, Reverse(OrderedFloat(new_label)));
-                        }
-                    },
-                    Vacant(entry) => {
-                        entry.insert(new_label);
-                        *previous_nodes.entry(neighbour).or_insert(current_node) = current_node;
-                        pq.push(neighbour, Reverse(OrderedFloat(new_label)));
-                    }
+    // Whether every route through the node with the label is heavier than the bound, every edge
+    // weighs at least its length. The margin covers rounding and the padding of `RiskMap::length_m`.
+    fn exceeds_bound(&self, label: f64, node: Coord<i16>, target: Coord<i16>, upper_bound: f64) -> bool {
+        label + self.risk_map.length_m(node, target) > upper_bound * (1.0 + 1e-9) + 1e-6
+    }
+
+    // Dijkstra from both ends, always advancing the side with the lower queue head. Every cell labelled
+    // by both sides gives a route, the search stops once the two heads together are not lower than the
+    // weight of the best of them: no route through unsettled cells can be lighter.
+    fn search_bidirectional(&self, alpha: f64, upper_bound: f64) -> Option<Path> {
+        let (rows, cols) = (self.risk_map.map.rows(), self.risk_map.map.cols());
+        let mut forward = SearchSide::new(self.from, rows, cols);
+        let mut backward = SearchSide::new(self.to, rows, cols);
+        let mut best: Option<(f64, Coord<i16>)> = if self.from == self.to { Some((0.0, self.from)) } else { None };
+
+        while let (Some(forward_head), Some(backward_head)) = (forward.head(), backward.head()) {
+            if best.is_some_and(|(weight, _)| forward_head + backward_head >= weight) {
+                break;
+            }
+
+            let is_forward = forward_head <= backward_head;
+            let (side, other) = if is_forward { (&mut forward, &backward) } else { (&mut backward, &forward) };
+
+            let (current_node, _) = side.pq.pop().unwrap();
+            let current_label = side.labels.at(current_node);
+            let (edges, target) = if is_forward {
+                (self.edges_from(current_node, alpha), self.to)
+            } else {
+                (self.edges_to(current_node, alpha), self.from)
+            };
+
+            for (neighbour, weight) in edges {
+                let new_label = current_label + weight;
+
+                if self.exceeds_bound(new_label, neighbour, target, upper_bound) || !side.relax(neighbour, current_node, new_label) {
+                    continue;
+                }
+
+                let weight = new_label + other.labels.at(neighbour);
+                if weight < best.map_or(f64::INFINITY, |(best_weight, _)| best_weight) {
+                    best = Some((weight, neighbour));
                 }
             }
         }
 
-        return self.unwrap_path(&previous_nodes, &labels, alpha);
+        let (weight, meeting_node) = best?;
+
+        // From the destination to the meeting cell, then on to the origin
+        let mut path = vec![meeting_node];
+        while *path.last().unwrap() != self.to {
+            path.push(backward.previous_nodes.at(*path.last().unwrap()).unwrap());
+        }
+        path.reverse();
+
+        while *path.last().unwrap() != self.from {
+            path.push(forward.previous_nodes.at(*path.last().unwrap()).unwrap());
+        }
+
+        Some(self.path_of_nodes(path, weight, alpha))
     }
 
-    fn unwrap_path(&self, nodes_previous: &HashMap<Coord<i16>, Coord<i16>>, nodes_labels: &HashMap<Coord<i16>, f64>, alpha: f64) -> Path {
-        let mut path = vec![];
-        let mut total_risk = 0;
-        let mut total_length = 0.0;
+    // Dijkstra over (cell, heading) states, edges turning sharper than allowed are skipped
+    fn search_with_turns(&self, alpha: f64, turns: &TurnConstraint, upper_bound: f64) -> Option<Path> {
+        let max_turn = turns.max_turn_deg.to_radians();
+        let start: HeadingState = (self.from, Coord{x: 0, y: 0});
+
+        let mut labels: HashMap<HeadingState, f64> = HashMap::new();
+        let mut previous_states: HashMap<HeadingState, HeadingState> = HashMap::new();
+        let mut pq: PriorityQueue<HeadingState, Reverse<OrderedFloat<f64>>, DefaultHashBuilder> = PriorityQueue::with_default_hasher();
+
+        pq.push(start, Reverse(OrderedFloat(0.0)));
+        labels.insert(start, 0.0);
+
+        let mut reached: Option<HeadingState> = None;
+
+        while let Some((state, _)) = pq.pop() {
+            let (current_node, heading) = state;
+            let current_label = labels[&state];
 
-        let mut previous_node = &self.to;
+            if current_node == self.to {
+                reached = Some(state);
+                break;
+            }
 
-        while previous_node != &self.from {
-            path.push(*previous_node);
+            for (neighbour, weight) in self.edges_from(current_node, alpha) {
+                let direction = primitive_direction(Coord{x: neighbour.x - current_node.x, y: neighbour.y - current_node.y});
+                let turn = turn_angle(heading, direction);
 
-            let new_previous_node = nodes_previous.get(previous_node).unwrap();
+                if turn > max_turn + 1e-9 {
+                    continue;
+                }
 
-            total_risk += self.risk_map.risk(*previous_node, *new_previous_node, self.r_m);
-            total_length += self.risk_map.length_m(*previous_node, *new_previous_node);
+                let new_label = current_label + weight + turn * turns.penalty_m_per_rad;
+                let next: HeadingState = (neighbour, direction);
 
-            previous_node = new_previous_node;
+                if labels.get(&next).is_none_or(|label| *label > new_label) && !self.exceeds_bound(new_label, neighbour, self.to, upper_bound) {
+                    labels.insert(next, new_label);
+                    previous_states.insert(next, state);
+                    pq.push(next, Reverse(OrderedFloat(new_label)));
+                }
+            }
+        }
+
+        let mut state = reached?;
+        let weight = labels[&state];
+        let mut path = vec![state.0];
+
+        while state != start {
+            state = previous_states[&state];
+            path.push(state.0);
+        }
+
+        Some(self.path_of_nodes(path, weight, alpha))
+    }
+
+    // Replaces chains of legs by straight legs wherever the ground risk of the straight leg is at most
+    // the risk of the chain times (1 + tolerance). Straight legs never cross no-fly zones and keep
+    // within the turn constraint.
+    pub fn smooth_path(&self, path: &Path, tolerance: f64) -> Path {
+        let nodes = &path.path;
+
+        if nodes.len() < 3 {
+            return self.path_of_nodes(nodes.clone(), path.linear_combination_weight, path.alpha)
+        }
+
+        let max_turn = self.turn_constraint.map(|turns| turns.max_turn_deg.to_radians() + 1e-9);
+        let direction = |p1: Coord<i16>, p2: Coord<i16>| primitive_direction(Coord{x: p2.x - p1.x, y: p2.y - p1.y});
+
+        let mut smoothed = vec![nodes[0]];
+        let mut previous_direction = Coord{x: 0, y: 0};
+        let mut anchor = 0;
+
+        while anchor < nodes.len() - 1 {
+            let mut chain_risk = 0;
+            let mut end = anchor + 1;
+
+            for candidate in anchor + 1..nodes.len() {
+                chain_risk += self.risk_map.risk(nodes[candidate - 1], nodes[candidate], self.r_m);
+
+                if candidate == anchor + 1 {
+                    continue;
+                }
+
+                let (from, to) = (nodes[anchor], nodes[candidate]);
+                let merged_direction = direction(from, to);
+                let turns_ok = match max_turn {
+                    Some(max_turn) => turn_angle(previous_direction, merged_direction) <= max_turn
+                        && (candidate == nodes.len() - 1 || turn_angle(merged_direction, direction(to, nodes[candidate + 1])) <= max_turn),
+                    None => true
+                };
+
+                if turns_ok
+                    && self.risk_map.blocking_zone(from, to, self.r_m).is_none()
+                    && self.risk_map.risk(from, to, self.r_m) as f64 <= chain_risk as f64 * (1.0 + tolerance) {
+                    end = candidate;
+                }
+            }
+
+            smoothed.push(nodes[end]);
+            previous_direction = direction(nodes[anchor], nodes[end]);
+            anchor = end;
+        }
+
+        let mut res = self.path_of_nodes(smoothed, 0.0, path.alpha);
+        res.linear_combination_weight = res.risk as f64 * path.alpha + res.length_m;
+
+        res
+    }
+
+    // The weight of the edge in the search with the given alpha, `None` if a no-fly zone blocks it
+    fn edge_weight(&self, p1: Coord<i16>, p2: Coord<i16>, alpha: f64) -> Option<f64> {
+        if self.risk_map.blocking_zone(p1, p2, self.r_m).is_some() {
+            return None
+        }
+
+        Some(self.risk_map.risk(p1, p2, self.r_m) as f64 * alpha + self.risk_map.length_m(p1, p2))
+    }
+
+    // Names of the no-fly zones that blocked an edge leaving one of the waypoints of the path
+    pub fn constraining_zones(&self, path: &Path) -> Vec<String> {
+        let zones = match &self.risk_map.no_fly_zones {
+            Some(zones) => zones,
+            None => return vec![]
+        };
+
+        let mut indices: Vec<usize> = path.path.iter()
+            .flat_map(|node| self.neighbours(*node)
+                .filter_map(move |neighbour| self.risk_map.blocking_zone(*node, neighbour, self.r_m)))
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+
+        indices.into_iter().map(|i| zones.zones[i].name.clone()).collect()
+    }
+
+    // Splits the path into its legs, starting at the origin
+    pub fn legs(&self, path: &Path, air_risk_instance: &AirRiskInstance) -> Vec<Leg> {
+        let mut legs: Vec<Leg> = vec![];
+        let (mut length_m, mut ground_risk, mut traffic, mut length_px) = (0.0, 0, 0.0, 0);
+
+        // The path is stored from the destination to the origin
+        for i in (0..path.path.len() - 1).rev() {
+            let (to, from) = (path.path[i], path.path[i+1]);
+            let mut leg_length_m = self.risk_map.length_m(to, from);
+            let mut leg_ground_risk = self.risk_map.risk(to, from, self.r_m);
+
+            // As in `path_of_nodes`
+            if legs.is_empty() {
+                leg_length_m += self.risk_map.length_m(from, from);
+                leg_ground_risk += self.risk_map.risk(from, from, self.r_m);
+            }
+            // Sampled in the stored direction, the same pixels as in `AirRiskInstance::compute_air_risk`
+            let (leg_traffic, leg_px) = air_risk_instance.leg_traffic(path, i);
+
+            length_m += leg_length_m;
+            ground_risk += leg_ground_risk;
+            traffic += leg_traffic;
+            length_px += leg_px;
+
+            legs.push(Leg{
+                from,
+                to,
+                length_m: leg_length_m,
+                ground_risk: leg_ground_risk,
+                air_risk: air_risk_instance.air_risk_of(leg_traffic, leg_px),
+                cumulative_length_m: length_m,
+                cumulative_ground_risk: ground_risk,
+                cumulative_air_risk: air_risk_instance.air_risk_of(traffic, length_px)
+            });
+        }
+
+        legs
+    }
+
+    fn unwrap_path(&self, labels: &dyn Labels, alpha: f64) -> Option<Path> {
+        let mut path = vec![];
+
+        let mut previous_node = self.to;
+
+        while previous_node != self.from {
+            path.push(previous_node);
+
+            previous_node = labels.previous(previous_node)?;
         }
 
         path.push(self.from);
-        total_risk += self.risk_map.risk(*previous_node, self.from, self.r_m);
-        total_length += self.risk_map.length_m(*previous_node, self.from);
 
-        return Path{
+        Some(self.path_of_nodes(path, labels.label(self.to), alpha))
+    }
+
+    // The weight of the route of the path in the searches with the given alpha
+    fn route_weight(&self, path: &Path, alpha: f64) -> f64 {
+        let mut weight = 0.0;
+        let mut heading = Coord{x: 0, y: 0};
+
+        // The path is stored from the destination to the origin
+        for leg in path.path.windows(2).rev() {
+            let (from, to) = (leg[1], leg[0]);
+
+            weight += match self.waypoint_graph {
+                Some(graph) => graph.edges_from(from).iter().find(|edge| edge.to == to).map(|edge| edge.risk as f64 * alpha + edge.length_m),
+                None => self.edge_weight(from, to, alpha)
+            }.unwrap_or(f64::INFINITY);
+
+            if let Some(turns) = &self.turn_constraint {
+                let direction = primitive_direction(Coord{x: to.x - from.x, y: to.y - from.y});
+                weight += turn_angle(heading, direction) * turns.penalty_m_per_rad;
+                heading = direction;
+            }
+        }
+
+        weight
+    }
+
+    // A path through the nodes, given from the destination to the origin
+    fn path_of_nodes(&self, path: Vec<Coord<i16>>, weight: f64, alpha: f64) -> Path {
+        let mut total_risk = 0;
+        let mut total_length = 0.0;
+
+        for leg in path.windows(2) {
+            total_risk += self.risk_map.risk(leg[0], leg[1], self.r_m);
+            total_length += self.risk_map.length_m(leg[0], leg[1]);
+        }
+
+        // The origin counts as a leg of zero length
+        total_risk += self.risk_map.risk(self.from, self.from, self.r_m);
+        total_length += self.risk_map.length_m(self.from, self.from);
+
+        Path{
             path,
-            linear_combination_weight: *nodes_labels.get(&self.to).unwrap(),
+            linear_combination_weight: weight,
             risk: total_risk,
             length_m: total_length,
-            alpha: alpha
+            alpha,
+            altitude_profile: None
+        }
+    }
+}
+
+// The labels and the queue of one direction of the bidirectional search, the labels are indexed like the risk map
+struct SearchSide {
+    labels: Grid<f64>,
+    previous_nodes: Grid<Option<Coord<i16>>>,
+    pq: PriorityQueue<Coord<i16>, Reverse<OrderedFloat<f64>>, DefaultHashBuilder>
+}
+
+impl SearchSide {
+    fn new(start: Coord<i16>, rows: usize, cols: usize) -> Self {
+        let mut side = Self{ labels: Grid::new(rows, cols, f64::INFINITY), previous_nodes: Grid::new(rows, cols, None), pq: PriorityQueue::with_default_hasher() };
+
+        side.labels.set(start.y as usize, start.x as usize, 0.0);
+        side.pq.push(start, Reverse(OrderedFloat(0.0)));
+
+        side
+    }
+
+    fn head(&self) -> Option<f64> {
+        self.pq.peek().map(|(_, label)| (label.0).0)
+    }
+
+    // Whether the new label improved the node
+    fn relax(&mut self, node: Coord<i16>, previous_node: Coord<i16>, label: f64) -> bool {
+        if self.labels.at(node) <= label {
+            return false
         }
+
+        self.labels.set(node.y as usize, node.x as usize, label);
+        self.previous_nodes.set(node.y as usize, node.x as usize, Some(previous_node));
+        self.pq.push(node, Reverse(OrderedFloat(label)));
+
+        true
     }
 }
 
@@ -163,4 +885,23 @@ impl Display for Path {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "linear_weight: {}, risk: {}, length: {}, path: {:?}", &self.linear_combination_weight, &self.risk, &self.length_m, &self.path)
     }
-}
\ No newline at end of file
+}
+
+// The direction divided by the gcd of its coordinates, so that parallel edges have the same direction
+fn primitive_direction(d: Coord<i16>) -> Coord<i16> {
+    let divisor = gcd(d.x, d.y).max(1);
+
+    Coord{x: d.x / divisor, y: d.y / divisor}
+}
+
+// The absolute heading change between two directions in radians, none at the origin
+fn turn_angle(d1: Coord<i16>, d2: Coord<i16>) -> f64 {
+    if d1 == (Coord{x: 0, y: 0}) {
+        return 0.0
+    }
+
+    let cross = (d1.x as f64) * (d2.y as f64) - (d1.y as f64) * (d2.x as f64);
+    let dot = (d1.x as f64) * (d2.x as f64) + (d1.y as f64) * (d2.y as f64);
+
+    cross.atan2(dot).abs()
+}
@@ -0,0 +1,301 @@
+#[cfg(test)]
+mod tests {
+    use super::super::{*};
+    use crate::zones::zones::{Zone, ZoneMap};
+    use crate::risks::risks::NeighbourhoodShape;
+    use crate::test_maps::test_maps::{self, with_wall};
+
+    fn risk_map() -> RiskMap {
+        let mut map = Grid::new(10, 10, 1);
+        map.set(2, 2, 100);
+
+        test_maps::risk_map(map)
+    }
+
+    // A wall over column 5 with a gap at the bottom, the routes from one side to the other have to go down and back up
+    fn walled_risk_map(density: i32) -> RiskMap {
+        with_wall(risk_map(), 0..8, 5..6, density)
+    }
+
+    #[test]
+    fn test_legs() {
+        let risk_map = risk_map();
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 0, y: 0}, Coord{x: 4, y: 4}, 5, 0.5);
+        let mut traffic = Grid::new(10, 10, 0);
+        traffic.set(1, 4, 6);
+        let air_risk_instance = AirRiskInstance::new(traffic, 2);
+
+        let path = Path{
+            path: vec![Coord{x: 4, y: 4}, Coord{x: 4, y: 0}, Coord{x: 0, y: 0}],
+            linear_combination_weight: 0.0,
+            risk: 0,
+            length_m: 8.0,
+            alpha: 1.0,
+            altitude_profile: None
+        };
+
+        let legs = inst.legs(&path, &air_risk_instance);
+
+        assert_eq!(legs.len(), 2);
+        assert_eq!((legs[0].from, legs[0].to), (Coord{x: 0, y: 0}, Coord{x: 4, y: 0}));
+        assert_eq!((legs[1].from, legs[1].to), (Coord{x: 4, y: 0}, Coord{x: 4, y: 4}));
+
+        assert_eq!(legs[0].ground_risk, risk_map.risk(Coord{x: 4, y: 0}, Coord{x: 0, y: 0}, 0.5) + risk_map.risk(Coord{x: 0, y: 0}, Coord{x: 0, y: 0}, 0.5));
+        assert_eq!(legs[1].cumulative_ground_risk, legs[0].ground_risk + legs[1].ground_risk);
+        assert!((legs[1].cumulative_length_m - 8.0).abs() < 1e-5);
+
+        assert_eq!(legs[0].air_risk, 0.0);
+        assert!(legs[1].air_risk > 0.0);
+        assert_eq!(legs[1].cumulative_air_risk, air_risk_instance.compute_air_risk(&path));
+    }
+
+    #[test]
+    fn test_legs_add_up_to_the_path() {
+        let risk_map = risk_map();
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 1, y: 1}, Coord{x: 7, y: 4}, 2, 0.5);
+        let path = inst.run_with_alpha(1.0);
+
+        let legs = inst.legs(&path, &AirRiskInstance::new(Grid::new(10, 10, 0), 1));
+        let last = legs.last().unwrap();
+
+        assert_eq!(last.cumulative_ground_risk, path.risk);
+        assert_eq!(legs.iter().map(|leg| leg.ground_risk).sum::<i32>(), path.risk);
+        assert!((last.cumulative_length_m - path.length_m).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_no_fly_zone() {
+        let mut risk_map = risk_map();
+        let wall = Zone{
+            name: "prison".to_string(),
+            polygon: vec![Coord{x: 3.5, y: -1.0}, Coord{x: 4.5, y: -1.0}, Coord{x: 4.5, y: 6.5}, Coord{x: 3.5, y: 6.5}],
+            holes: vec![]
+        };
+        risk_map.no_fly_zones = Some(ZoneMap::new(vec![wall], 10, 10));
+
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 1, y: 1}, Coord{x: 7, y: 1}, 2, 0.5);
+        let path = inst.run_with_alpha(0.0);
+
+        assert!(path.path.iter().all(|c| c.x != 4 || c.y > 6));
+        assert!(path.path.windows(2).all(|leg| risk_map.blocking_zone(leg[0], leg[1], 0.5).is_none()));
+        assert_eq!(inst.constraining_zones(&path), vec!["prison".to_string()]);
+    }
+
+    fn front() -> Vec<Path> {
+        [(100, 10.0), (60, 12.0), (30, 20.0)].iter()
+            .map(|(risk, length_m)| Path{path: vec![], linear_combination_weight: 0.0, risk: *risk, length_m: *length_m, alpha: 0.0, altitude_profile: None})
+            .collect()
+    }
+
+    #[test]
+    fn test_sub_pixel_no_fly_zone() {
+        let mut risk_map = risk_map();
+        // A mast off the centre of pixel (4, 1)
+        let mast = Zone{
+            name: "mast".to_string(),
+            polygon: vec![Coord{x: 4.2, y: 0.8}, Coord{x: 4.3, y: 0.8}, Coord{x: 4.3, y: 0.9}, Coord{x: 4.2, y: 0.9}],
+            holes: vec![]
+        };
+        risk_map.no_fly_zones = Some(ZoneMap::new(vec![mast], 10, 10));
+
+        assert_eq!(risk_map.blocking_zone(Coord{x: 1, y: 1}, Coord{x: 7, y: 1}, 0.5), Some(0));
+
+        let path = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 1, y: 1}, Coord{x: 7, y: 1}, 6, 0.5).run_with_alpha(0.0);
+        assert!(path.path.windows(2).all(|leg| risk_map.blocking_zone(leg[0], leg[1], 0.5).is_none()));
+        assert!(path.path.len() > 2);
+    }
+
+    #[test]
+    fn test_max_length() {
+        assert_eq!(best_feasible_path(front(), RouteConstraint::MaxLength(15.0)).unwrap().risk, 60);
+        assert_eq!(best_feasible_path(front(), RouteConstraint::MaxLength(20.0)).unwrap().risk, 30);
+        assert_eq!(best_feasible_path(front(), RouteConstraint::MaxLength(9.0)).unwrap_err(),
+                   NoFeasibleRoute{constraint: RouteConstraint::MaxLength(9.0), closest: 10.0});
+    }
+
+    #[test]
+    fn test_max_risk() {
+        assert_eq!(best_feasible_path(front(), RouteConstraint::MaxRisk(100)).unwrap().length_m, 10.0);
+        assert_eq!(best_feasible_path(front(), RouteConstraint::MaxRisk(59)).unwrap().length_m, 20.0);
+        assert_eq!(best_feasible_path(front(), RouteConstraint::MaxRisk(10)).unwrap_err().closest, 30.0);
+    }
+
+    #[test]
+    fn test_constrained_path() {
+        let risk_map = risk_map();
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 1, y: 2}, Coord{x: 4, y: 2}, 2, 0.5);
+        let shortest = inst.run_with_alpha(0.0);
+
+        let path = inst.compute_constrained_path(RouteConstraint::MaxLength(shortest.length_m * 2.0)).unwrap();
+        assert!(path.length_m <= shortest.length_m * 2.0);
+        assert!(path.risk < shortest.risk);
+
+        assert!(inst.compute_constrained_path(RouteConstraint::MaxLength(shortest.length_m / 2.0)).is_err());
+    }
+
+    #[test]
+    fn test_constrained_path_off_the_hull() {
+        // Straight across a dense pixel, through a less dense one on a short detour, or a long detour
+        // around both. The short detour is not on the hull of the Pareto front, no alpha finds it.
+        let risk_map = with_wall(with_wall(test_maps::risk_map(Grid::new(12, 21, 0)), 10..11, 10..11, 100), 6..7, 10..11, 45);
+        let waypoints = vec![Coord{x: 0, y: 10}, Coord{x: 20, y: 10}, Coord{x: 10, y: 6}, Coord{x: 10, y: 0}];
+        let graph = WaypointGraph::new(&risk_map, waypoints, 30.0, 0.5);
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 0, y: 10}, Coord{x: 20, y: 10}, 1, 0.5).with_waypoint_graph(&graph);
+
+        let front = inst.compute_pareto_apx_paths();
+        assert_eq!(front.iter().map(|p| p.risk).collect::<Vec<_>>(), vec![100, 0]);
+        assert_eq!(best_feasible_path(front, RouteConstraint::MaxLength(22.0)).unwrap().risk, 100);
+
+        let path = inst.compute_constrained_path(RouteConstraint::MaxLength(22.0)).unwrap();
+        assert_eq!(path.path, vec![Coord{x: 20, y: 10}, Coord{x: 10, y: 6}, Coord{x: 0, y: 10}]);
+        assert_eq!(path.risk, 90);
+
+        let path = inst.compute_constrained_path(RouteConstraint::MaxRisk(95)).unwrap();
+        assert_eq!(path.path, vec![Coord{x: 20, y: 10}, Coord{x: 10, y: 6}, Coord{x: 0, y: 10}]);
+        assert!(path.length_m < 22.0);
+    }
+
+    #[test]
+    fn test_turn_angle() {
+        assert_eq!(primitive_direction(Coord{x: 4, y: -2}), Coord{x: 2, y: -1});
+        assert_eq!(primitive_direction(Coord{x: 0, y: -3}), Coord{x: 0, y: -1});
+
+        assert_eq!(turn_angle(Coord{x: 0, y: 0}, Coord{x: -1, y: 0}), 0.0);
+        assert_eq!(turn_angle(Coord{x: 1, y: 0}, Coord{x: 1, y: 0}), 0.0);
+        assert!((turn_angle(Coord{x: 1, y: 0}, Coord{x: 0, y: -1}) - std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+        assert!((turn_angle(Coord{x: 1, y: 0}, Coord{x: -1, y: 0}) - std::f64::consts::PI).abs() < 1e-9);
+    }
+
+    fn turns(path: &Path) -> Vec<f64> {
+        let directions: Vec<Coord<i16>> = path.path.windows(2)
+            .map(|leg| primitive_direction(Coord{x: leg[0].x - leg[1].x, y: leg[0].y - leg[1].y}))
+            .collect();
+
+        directions.windows(2).map(|d| turn_angle(d[0], d[1]).to_degrees()).collect()
+    }
+
+    #[test]
+    fn test_turn_constraint() {
+        let risk_map = walled_risk_map(100000);
+
+        // Without the constraint the route around the wall turns by up to 53 degrees
+        let unconstrained = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 2, y: 2}, Coord{x: 8, y: 2}, 2, 0.5)
+            .run_with_alpha(1.0);
+        assert!(turns(&unconstrained).iter().any(|t| *t > 45.0));
+
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 2, y: 2}, Coord{x: 8, y: 2}, 2, 0.5)
+            .with_turn_constraint(45.0, 0.0);
+        let path = inst.run_with_alpha(1.0);
+
+        assert_eq!((path.path[0], *path.path.last().unwrap()), (Coord{x: 8, y: 2}, Coord{x: 2, y: 2}));
+        assert!(turns(&path).iter().all(|t| *t <= 45.0 + 1e-9));
+        assert!(path.length_m >= unconstrained.length_m);
+    }
+
+    #[test]
+    fn test_turn_penalty() {
+        let risk_map = risk_map();
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 1, y: 1}, Coord{x: 7, y: 3}, 3, 0.5)
+            .with_turn_constraint(180.0, 1000.0);
+        let path = inst.run_with_alpha(0.0);
+
+        assert!(turns(&path).iter().all(|t| *t == 0.0));
+        assert!((path.linear_combination_weight - path.length_m).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_smooth_straight_path() {
+        let risk_map = risk_map();
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 0, y: 6}, Coord{x: 9, y: 6}, 2, 0.5);
+        let path = inst.run_with_alpha(1.0);
+        assert!(path.path.len() > 2);
+
+        let smoothed = inst.smooth_path(&path, 0.0);
+
+        assert_eq!(smoothed.path, vec![Coord{x: 9, y: 6}, Coord{x: 0, y: 6}]);
+        assert!(smoothed.risk <= path.risk);
+        assert!(smoothed.length_m <= path.length_m + 1e-6);
+    }
+
+    #[test]
+    fn test_smooth_around_obstacle() {
+        let risk_map = walled_risk_map(100000);
+
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 2, y: 2}, Coord{x: 8, y: 2}, 2, 0.5);
+        let path = inst.run_with_alpha(1.0);
+        let smoothed = inst.smooth_path(&path, 0.0);
+
+        assert!(smoothed.path.len() >= 3 && smoothed.path.len() <= path.path.len());
+        assert!(smoothed.risk <= path.risk);
+        assert!(smoothed.risk < 100000);
+        assert_eq!((smoothed.path[0], *smoothed.path.last().unwrap()), (path.path[0], *path.path.last().unwrap()));
+    }
+
+    #[test]
+    fn test_primitive_neighbourhood() {
+        let risk_map = risk_map();
+        let square = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 0, y: 0}, Coord{x: 8, y: 5}, 3, 0.5);
+        let primitive = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 0, y: 0}, Coord{x: 8, y: 5}, 3, 0.5)
+            .with_neighbourhood(Neighbourhood{shape: NeighbourhoodShape::Square, primitive_only: true});
+
+        let (square_path, primitive_path) = (square.run_with_alpha(0.0), primitive.run_with_alpha(0.0));
+
+        assert!((square_path.length_m - primitive_path.length_m).abs() < 1e-6);
+        assert!(primitive_path.path.windows(2).all(|leg| gcd(leg[0].x - leg[1].x, leg[0].y - leg[1].y) == 1));
+    }
+
+    #[test]
+    fn test_bidirectional_search() {
+        let risk_map = walled_risk_map(1000);
+
+        for (from, to) in [(Coord{x: 2, y: 2}, Coord{x: 8, y: 2}), (Coord{x: 9, y: 0}, Coord{x: 0, y: 9}), (Coord{x: 3, y: 3}, Coord{x: 3, y: 3})] {
+            let inst = BicriteriaDijkstraInstance::new(&risk_map, from, to, 2, 0.5);
+            let bidirectional = inst.clone().with_bidirectional_search();
+
+            for alpha in [0.0, 0.01, 1.0] {
+                let (expected, path) = (inst.run_with_alpha(alpha), bidirectional.run_with_alpha(alpha));
+
+                assert!((path.linear_combination_weight - expected.linear_combination_weight).abs() < 1e-6);
+                assert_eq!((path.path[0], *path.path.last().unwrap()), (to, from));
+            }
+        }
+    }
+
+    #[test]
+    fn test_hull_bounds() {
+        let risk_map = walled_risk_map(1000);
+
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 2, y: 2}, Coord{x: 8, y: 2}, 2, 0.5);
+        let bounded = inst.clone().with_hull_bounds();
+
+        let (front, bounded_front) = (inst.compute_pareto_apx_paths(), bounded.compute_pareto_apx_paths());
+
+        assert_eq!(front.iter().map(|p| (p.risk, p.alpha)).collect::<Vec<_>>(), bounded_front.iter().map(|p| (p.risk, p.alpha)).collect::<Vec<_>>());
+
+        // Searching between the two extreme routes prunes the cells far from both of them
+        let (shortest, safest) = (&front[0], &front[front.len() - 1]);
+        let upper_bound = inst.route_weight(shortest, 0.01).min(inst.route_weight(safest, 0.01));
+        let labels = inst.search(0.01, Some(inst.to), upper_bound).to_grid(10, 10);
+        let all_labels = inst.search(0.01, Some(inst.to), f64::INFINITY).to_grid(10, 10);
+
+        assert!((labels.at(inst.to) - all_labels.at(inst.to)).abs() < 1e-6);
+        assert!(labels.values().iter().filter(|l| l.is_finite()).count() < all_labels.values().iter().filter(|l| l.is_finite()).count());
+    }
+
+    #[test]
+    fn test_search_backends() {
+        let risk_map = walled_risk_map(1000);
+
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 2, y: 2}, Coord{x: 8, y: 2}, 2, 0.5);
+        let expected = inst.one_to_all_labels(0.5);
+
+        for storage in [LabelStorage::Hash, LabelStorage::Dense] {
+            for queue in [QueueKind::Binary, QueueKind::Radix] {
+                let backend = inst.clone().with_search_backend(storage, queue);
+
+                assert_eq!(backend.one_to_all_labels(0.5), expected);
+                assert_eq!(backend.run_with_alpha(0.5).linear_combination_weight, expected.at(inst.to));
+            }
+        }
+    }
+}
@@ -0,0 +1 @@
+pub mod grid;
\ No newline at end of file
@@ -0,0 +1,93 @@
+#[cfg(test)]
+#[path = "tests.rs"]
+mod tests;
+
+use crate::risks::risks::Coord;
+
+// A rectangular grid stored row by row.
+// Rows go along the y axis of the map and columns along the x axis, so the cell of Coord{x, y}
+// is get(y, x). Sources stored the other way around have to be transposed when loaded.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Grid<T> {
+    rows: usize,
+    cols: usize,
+    data: Vec<T>
+}
+
+impl<T: Copy> Grid<T> {
+    pub fn new(rows: usize, cols: usize, value: T) -> Self {
+        Self{ rows, cols, data: vec![value; rows * cols] }
+    }
+
+    pub fn from_vec(rows: usize, cols: usize, data: Vec<T>) -> Self {
+        if data.len() != rows * cols {
+            panic!("A {}x{} grid needs {} values, got {}", rows, cols, rows * cols, data.len());
+        }
+
+        Self{ rows, cols, data }
+    }
+
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Self {
+        let cols = rows.first().map_or(0, |row| row.len());
+
+        if rows.iter().any(|row| row.len() != cols) {
+            panic!("All rows of a grid must have the same length");
+        }
+
+        Self{ rows: rows.len(), cols, data: rows.into_iter().flatten().collect() }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> T {
+        self.data[self.index(row, col)]
+    }
+
+    pub fn set(&mut self, row: usize, col: usize, value: T) {
+        let index = self.index(row, col);
+        self.data[index] = value;
+    }
+
+    pub fn at(&self, coord: Coord<i16>) -> T {
+        self.get(coord.y as usize, coord.x as usize)
+    }
+
+    pub fn row(&self, row: usize) -> &[T] {
+        &self.data[row * self.cols..(row + 1) * self.cols]
+    }
+
+    pub fn values(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn to_rows(&self) -> Vec<Vec<T>> {
+        (0..self.rows).map(|r| self.row(r).to_vec()).collect()
+    }
+
+    pub fn transposed(&self) -> Self {
+        let data = (0..self.cols)
+            .flat_map(|c| (0..self.rows).map(move |r| (r, c)))
+            .map(|(r, c)| self.get(r, c))
+            .collect();
+
+        Self{ rows: self.cols, cols: self.rows, data }
+    }
+
+    pub fn same_shape<U: Copy>(&self, other: &Grid<U>) -> bool {
+        self.rows == other.rows() && self.cols == other.cols()
+    }
+
+    fn index(&self, row: usize, col: usize) -> usize {
+        if row >= self.rows || col >= self.cols {
+            panic!("Cell ({}, {}) is outside of the {}x{} grid", row, col, self.rows, self.cols);
+        }
+
+        row * self.cols + col
+    }
+}
@@ -0,0 +1,38 @@
+#[cfg(test)]
+mod tests {
+    use super::super::{*};
+
+    #[test]
+    fn test_rows_and_cols() {
+        let grid = Grid::from_rows(vec![vec![1, 2, 3], vec![4, 5, 6]]);
+
+        assert_eq!((grid.rows(), grid.cols()), (2, 3));
+        assert_eq!(grid.get(1, 0), 4);
+        assert_eq!(grid.at(Coord{x: 2, y: 0}), 3);
+        assert_eq!(grid.row(1), &[4, 5, 6]);
+    }
+
+    #[test]
+    fn test_transposed() {
+        let grid = Grid::from_rows(vec![vec![1, 2, 3], vec![4, 5, 6]]);
+        let transposed = grid.transposed();
+
+        assert_eq!(transposed.to_rows(), vec![vec![1, 4], vec![2, 5], vec![3, 6]]);
+        assert_eq!(transposed.transposed(), grid);
+        assert!(!grid.same_shape(&transposed));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_out_of_bounds() {
+        let grid = Grid::new(2, 3, 0);
+
+        grid.get(0, 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_ragged_rows() {
+        Grid::from_rows(vec![vec![1, 2], vec![3]]);
+    }
+}
@@ -0,0 +1 @@
+pub mod grid_io;
\ No newline at end of file
@@ -0,0 +1,339 @@
+#[cfg(test)]
+#[path = "tests.rs"]
+mod tests;
+
+use std::fs::File;
+use std::io::{self, Read, Write, Seek, BufReader, BufWriter, Error, ErrorKind};
+use std::path::Path;
+use zip::{ZipArchive, ZipWriter};
+use zip::write::FileOptions;
+use crate::grid::grid::Grid;
+
+// Binary grid format, all numbers little-endian:
+//   magic b"GRID", version: u32, rows: u32, cols: u32, followed by rows*cols i32 values row by row.
+const MAGIC: &[u8; 4] = b"GRID";
+const VERSION: u32 = 1;
+
+pub fn write_grid<W: Write>(writer: &mut W, grid: &Grid<i32>) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(grid.rows() as u32).to_le_bytes())?;
+    writer.write_all(&(grid.cols() as u32).to_le_bytes())?;
+
+    let mut buffer: Vec<u8> = Vec::with_capacity(grid.values().len() * 4);
+    for value in grid.values() {
+        buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    writer.write_all(&buffer)
+}
+
+pub fn read_grid<R: Read>(reader: &mut R) -> io::Result<Grid<i32>> {
+    let mut header = [0u8; 16];
+    reader.read_exact(&mut header)?;
+
+    if &header[0..4] != MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "Not a binary grid file"));
+    }
+
+    let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    if version != VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Unsupported binary grid version {}", version)));
+    }
+
+    let rows = u32::from_le_bytes([header[8], header[9], header[10], header[11]]) as usize;
+    let cols = u32::from_le_bytes([header[12], header[13], header[14], header[15]]) as usize;
+
+    let data = read_data(reader, rows, cols, 4)?;
+
+    let values: Vec<i32> = data.chunks_exact(4)
+        .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+
+    Ok(Grid::from_vec(rows, cols, values))
+}
+
+// Reads the `rows` x `cols` elements of `size` bytes following a header. The sizes come from the file, so they
+// are not trusted: the buffer only grows with what the file actually holds.
+fn read_data<R: Read>(reader: &mut R, rows: usize, cols: usize, size: usize) -> io::Result<Vec<u8>> {
+    let len = rows.checked_mul(cols)
+        .and_then(|n| n.checked_mul(size))
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("A {} x {} grid is too large", rows, cols)))?;
+
+    let mut data = vec![];
+    reader.take(len as u64).read_to_end(&mut data)?;
+
+    if data.len() != len {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Expected {} bytes of values, the file holds {}", len, data.len())));
+    }
+
+    Ok(data)
+}
+
+// A 2D array read from a NumPy .npy file. Values of any numeric dtype are widened to f64
+// and stored in C (row-major) order.
+#[derive(Clone, PartialEq, Debug)]
+pub struct NpyArray {
+    pub rows: usize,
+    pub cols: usize,
+    pub data: Vec<f64>
+}
+
+impl NpyArray {
+    // Densities and traffic counts are integers, fractional values are rounded
+    pub fn to_grid(&self) -> Grid<i32> {
+        Grid::from_vec(self.rows, self.cols, self.data.iter().map(|v| v.round() as i32).collect())
+    }
+}
+
+pub trait NpyElement: Copy {
+    const DESCR: &'static str;
+    fn write_le<W: Write>(&self, writer: &mut W) -> io::Result<()>;
+}
+
+impl NpyElement for i32 {
+    const DESCR: &'static str = "<i4";
+
+    fn write_le<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.to_le_bytes())
+    }
+}
+
+impl NpyElement for f64 {
+    const DESCR: &'static str = "<f8";
+
+    fn write_le<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.to_le_bytes())
+    }
+}
+
+const NPY_MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+pub fn write_npy<W: Write, T: NpyElement>(writer: &mut W, grid: &Grid<T>) -> io::Result<()> {
+    let (rows, cols) = (grid.rows(), grid.cols());
+
+    // Version 1.0 header, padded with spaces so that the data starts at a multiple of 64 bytes
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}", T::DESCR, rows, cols);
+    let unpadded = NPY_MAGIC.len() + 2 + 2 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    writer.write_all(NPY_MAGIC)?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+
+    let mut buffer: Vec<u8> = Vec::with_capacity(rows * cols * 8);
+    for value in grid.values() {
+        value.write_le(&mut buffer)?;
+    }
+
+    writer.write_all(&buffer)
+}
+
+pub fn read_npy<R: Read>(reader: &mut R) -> io::Result<NpyArray> {
+    let mut preamble = [0u8; 8];
+    reader.read_exact(&mut preamble)?;
+
+    if &preamble[0..6] != NPY_MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "Not a .npy file"));
+    }
+
+    let header_len = match preamble[6] {
+        1 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_le_bytes(len) as usize
+        },
+        2 | 3 => {
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len)?;
+            u32::from_le_bytes(len) as usize
+        },
+        version => return Err(invalid_npy(&format!("unsupported version {}", version)))
+    };
+
+    let mut header = vec![];
+    reader.take(header_len as u64).read_to_end(&mut header)?;
+    if header.len() != header_len {
+        return Err(invalid_npy("truncated header"));
+    }
+    let header = String::from_utf8_lossy(&header);
+
+    let descr = npy_header_value(&header, "descr")?.trim_matches(|c| c == '\'' || c == '"').to_string();
+    let fortran_order = npy_header_value(&header, "fortran_order")? == "True";
+    let shape: Vec<usize> = npy_header_value(&header, "shape")?
+        .trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .map(|d| d.trim())
+        .filter(|d| !d.is_empty())
+        .map(|d| d.parse::<usize>().map_err(|_| invalid_npy("malformed shape")))
+        .collect::<io::Result<Vec<usize>>>()?;
+
+    if shape.len() != 2 {
+        return Err(invalid_npy(&format!("expected a 2D array, got shape {:?}", shape)));
+    }
+
+    let (rows, cols) = (shape[0], shape[1]);
+    let (little_endian, kind, size) = parse_descr(&descr)?;
+
+    let raw = read_data(reader, rows, cols, size)?;
+
+    let values: Vec<f64> = raw.chunks_exact(size)
+        .map(|b| {
+            let mut bytes = b.to_vec();
+            if !little_endian {
+                bytes.reverse();
+            }
+            decode_element(kind, &bytes)
+        })
+        .collect();
+
+    let data = if fortran_order {
+        (0..rows * cols).map(|i| values[(i % cols) * rows + i / cols]).collect()
+    } else {
+        values
+    };
+
+    Ok(NpyArray{ rows, cols, data })
+}
+
+// Reads the array called `name` from an .npz archive, or its only array if no name is given
+pub fn read_npz<R: Read + Seek>(reader: R, name: Option<&str>) -> io::Result<NpyArray> {
+    let mut archive = ZipArchive::new(reader).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+    let entry_name = match name {
+        Some(name) => format!("{}.npy", name),
+        None if archive.len() == 1 => archive.by_index(0).map_err(|e| Error::new(ErrorKind::InvalidData, e))?.name().to_string(),
+        None => return Err(Error::new(ErrorKind::InvalidInput, "The .npz file holds several arrays, one has to be chosen by name"))
+    };
+
+    let mut entry = archive.by_name(&entry_name).map_err(|e| Error::new(ErrorKind::NotFound, e))?;
+
+    read_npy(&mut entry)
+}
+
+pub fn write_npz<W: Write + Seek, T: NpyElement>(writer: W, arrays: &[(&str, &Grid<T>)]) -> io::Result<()> {
+    let mut archive = ZipWriter::new(writer);
+
+    for (name, grid) in arrays {
+        archive.start_file(format!("{}.npy", name), FileOptions::default()).map_err(Error::other)?;
+        write_npy(&mut archive, grid)?;
+    }
+
+    archive.finish().map_err(Error::other)?;
+
+    Ok(())
+}
+
+fn invalid_npy(reason: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid .npy header: {}", reason))
+}
+
+// Returns the raw text of a value in the header dictionary, e.g. "'<i4'" for "descr"
+fn npy_header_value<'a>(header: &'a str, key: &str) -> io::Result<&'a str> {
+    let key_start = header.find(&format!("'{}'", key))
+        .or_else(|| header.find(&format!("\"{}\"", key)))
+        .ok_or_else(|| invalid_npy(&format!("missing {}", key)))?;
+    let rest = header[key_start + key.len() + 2..].trim_start();
+    let rest = rest.strip_prefix(':').ok_or_else(|| invalid_npy("malformed dictionary"))?.trim_start();
+
+    let end = if rest.starts_with('(') {
+        rest.find(')').map(|i| i + 1)
+    } else {
+        rest.find([',', '}'])
+    }.ok_or_else(|| invalid_npy("malformed dictionary"))?;
+
+    Ok(rest[..end].trim())
+}
+
+fn parse_descr(descr: &str) -> io::Result<(bool, char, usize)> {
+    let mut chars = descr.chars();
+    let byte_order = chars.next().ok_or_else(|| invalid_npy("empty descr"))?;
+    let kind = chars.next().ok_or_else(|| invalid_npy("empty descr"))?;
+    let size: usize = chars.as_str().parse().map_err(|_| invalid_npy(&format!("unsupported dtype {}", descr)))?;
+
+    let supported = matches!((kind, size), ('i', 1) | ('i', 2) | ('i', 4) | ('i', 8) | ('u', 1) | ('u', 2) | ('u', 4) | ('u', 8) | ('f', 4) | ('f', 8) | ('b', 1));
+    if !supported {
+        return Err(invalid_npy(&format!("unsupported dtype {}", descr)));
+    }
+
+    let little_endian = match byte_order {
+        '<' | '|' | '=' => true,
+        '>' => false,
+        _ => return Err(invalid_npy(&format!("unsupported byte order in {}", descr)))
+    };
+
+    Ok((little_endian, kind, size))
+}
+
+fn decode_element(kind: char, b: &[u8]) -> f64 {
+    match (kind, b.len()) {
+        ('i', 1) => b[0] as i8 as f64,
+        ('i', 2) => i16::from_le_bytes([b[0], b[1]]) as f64,
+        ('i', 4) => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+        ('i', 8) => i64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f64,
+        ('u', 1) | ('b', 1) => b[0] as f64,
+        ('u', 2) => u16::from_le_bytes([b[0], b[1]]) as f64,
+        ('u', 4) => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+        ('u', 8) => u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f64,
+        ('f', 4) => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+        ('f', 8) => f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+        _ => unreachable!()
+    }
+}
+
+pub fn save_grid(filename: &str, grid: &Grid<i32>) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(filename)?);
+
+    match extension(filename).as_str() {
+        "grid" => write_grid(&mut writer, grid)?,
+        "json" => serde_json::to_writer(&mut writer, &grid.to_rows())?,
+        "npy" => write_npy(&mut writer, grid)?,
+        "npz" => write_npz(&mut writer, &[("map", grid)])?,
+        ext => return Err(Error::new(ErrorKind::InvalidInput, format!("Unknown grid format: {}", ext)))
+    }
+
+    writer.flush()
+}
+
+// Saves a per-cell product (e.g. labels) as .npy, or as the array `name` of an .npz archive
+pub fn save_values(filename: &str, name: &str, values: &Grid<f64>) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(filename)?);
+
+    match extension(filename).as_str() {
+        "npy" => write_npy(&mut writer, values)?,
+        "npz" => write_npz(&mut writer, &[(name, values)])?,
+        ext => return Err(Error::new(ErrorKind::InvalidInput, format!("Unknown values format: {}", ext)))
+    }
+
+    writer.flush()
+}
+
+// Loads a grid from a binary grid file (.grid), a nested JSON array (.json) or a NumPy array (.npy, .npz)
+pub fn load_grid(filename: &str) -> io::Result<Grid<i32>> {
+    let mut reader = BufReader::new(File::open(filename)?);
+
+    match extension(filename).as_str() {
+        "grid" => read_grid(&mut reader),
+        "json" => {
+            let rows: Vec<Vec<i32>> = serde_json::from_reader(reader).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+            if rows.iter().any(|row| row.len() != rows[0].len()) {
+                return Err(Error::new(ErrorKind::InvalidData, "All rows of a grid must have the same length"));
+            }
+
+            Ok(Grid::from_rows(rows))
+        },
+        "npy" => Ok(read_npy(&mut reader)?.to_grid()),
+        "npz" => Ok(read_npz(reader, None)?.to_grid()),
+        ext => Err(Error::new(ErrorKind::InvalidInput, format!("Unknown grid format: {}", ext)))
+    }
+}
+
+pub fn extension(filename: &str) -> String {
+    Path::new(filename).extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase()
+}
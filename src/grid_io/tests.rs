@@ -0,0 +1,147 @@
+#[cfg(test)]
+mod tests {
+    use super::super::{*};
+    use crate::grid::grid::Grid;
+
+    #[test]
+    fn test_binary_roundtrip() {
+        let grid = Grid::from_rows(vec![vec![1, 2, 3], vec![-4, 5, i32::MAX]]);
+
+        let mut buffer: Vec<u8> = vec![];
+        write_grid(&mut buffer, &grid).unwrap();
+
+        assert_eq!(buffer.len(), 16 + 6 * 4);
+        assert_eq!(&buffer[0..4], b"GRID");
+        assert_eq!(read_grid(&mut buffer.as_slice()).unwrap(), grid);
+    }
+
+    #[test]
+    fn test_wrong_magic() {
+        let buffer = b"NOPE\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+
+        assert_eq!(read_grid(&mut buffer.as_slice()).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_truncated_file() {
+        let mut buffer: Vec<u8> = vec![];
+        write_grid(&mut buffer, &Grid::from_rows(vec![vec![1, 2], vec![3, 4]])).unwrap();
+        buffer.truncate(buffer.len() - 2);
+
+        assert!(read_grid(&mut buffer.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_sizes() {
+        // The size of 2^32 - 1 x 2^32 - 1 values overflows
+        let mut buffer = b"GRID\x01\x00\x00\x00\xff\xff\xff\xff\xff\xff\xff\xff".to_vec();
+        buffer.extend_from_slice(&[0; 8]);
+
+        assert_eq!(read_grid(&mut buffer.as_slice()).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        // 100000 x 100000 values would be 40 GB, the file holds two
+        let mut buffer = b"GRID\x01\x00\x00\x00\xa0\x86\x01\x00\xa0\x86\x01\x00".to_vec();
+        buffer.extend_from_slice(&[0; 8]);
+
+        assert_eq!(read_grid(&mut buffer.as_slice()).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_extension() {
+        assert_eq!(extension("./data/map.json"), "json");
+        assert_eq!(extension("map.GRID"), "grid");
+        assert_eq!(extension("map"), "");
+    }
+
+    #[test]
+    fn test_npy_roundtrip() {
+        let grid = Grid::from_rows(vec![vec![1, 2, 3], vec![4, 5, -6]]);
+
+        let mut buffer: Vec<u8> = vec![];
+        write_npy(&mut buffer, &grid).unwrap();
+
+        assert_eq!(&buffer[0..6], b"\x93NUMPY");
+        assert_eq!((10 + u16::from_le_bytes([buffer[8], buffer[9]]) as usize) % 64, 0);
+
+        let array = read_npy(&mut buffer.as_slice()).unwrap();
+        assert_eq!((array.rows, array.cols), (2, 3));
+        assert_eq!(array.to_grid(), grid);
+    }
+
+    #[test]
+    fn test_npy_f64_roundtrip() {
+        let values = Grid::from_rows(vec![vec![0.5, f64::INFINITY], vec![-1.25, 3.0]]);
+
+        let mut buffer: Vec<u8> = vec![];
+        write_npy(&mut buffer, &values).unwrap();
+
+        assert_eq!(read_npy(&mut buffer.as_slice()).unwrap().data, vec![0.5, f64::INFINITY, -1.25, 3.0]);
+    }
+
+    fn npy_file(header: &str, data: &[u8]) -> Vec<u8> {
+        let mut buffer = b"\x93NUMPY\x01\x00".to_vec();
+        buffer.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        buffer.extend_from_slice(header.as_bytes());
+        buffer.extend_from_slice(data);
+
+        buffer
+    }
+
+    #[test]
+    fn test_npy_other_dtypes() {
+        let u8_file = npy_file("{'descr': '|u1', 'fortran_order': False, 'shape': (2, 2), }\n", &[1, 2, 3, 255]);
+        assert_eq!(read_npy(&mut u8_file.as_slice()).unwrap().to_grid().to_rows(), vec![vec![1, 2], vec![3, 255]]);
+
+        let be_file = npy_file("{'descr': '>i2', 'fortran_order': False, 'shape': (1, 2), }\n", &[0, 1, 1, 0]);
+        assert_eq!(read_npy(&mut be_file.as_slice()).unwrap().to_grid().to_rows(), vec![vec![1, 256]]);
+
+        let mut f4_data: Vec<u8> = vec![];
+        f4_data.extend_from_slice(&1.4f32.to_le_bytes());
+        f4_data.extend_from_slice(&2.6f32.to_le_bytes());
+        let f4_file = npy_file("{'descr': '<f4', 'fortran_order': False, 'shape': (2, 1), }\n", &f4_data);
+        assert_eq!(read_npy(&mut f4_file.as_slice()).unwrap().to_grid().to_rows(), vec![vec![1], vec![3]]);
+    }
+
+    #[test]
+    fn test_npy_fortran_order() {
+        // [[1, 2, 3], [4, 5, 6]] stored column by column
+        let data: Vec<u8> = [1i32, 4, 2, 5, 3, 6].iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+        let file = npy_file("{'descr': '<i4', 'fortran_order': True, 'shape': (2, 3), }\n", &data);
+
+        assert_eq!(read_npy(&mut file.as_slice()).unwrap().to_grid().to_rows(), vec![vec![1, 2, 3], vec![4, 5, 6]]);
+    }
+
+    #[test]
+    fn test_npy_not_2d() {
+        let file = npy_file("{'descr': '<i4', 'fortran_order': False, 'shape': (3,), }\n", &[0; 12]);
+
+        assert_eq!(read_npy(&mut file.as_slice()).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_npy_corrupt_shape() {
+        for shape in ["(100000, 100000)", "(18446744073709551615, 2)"] {
+            let header = format!("{{'descr': '<i4', 'fortran_order': False, 'shape': {}, }}\n", shape);
+
+            let mut buffer = b"\x93NUMPY\x01\x00".to_vec();
+            buffer.extend_from_slice(&(header.len() as u16).to_le_bytes());
+            buffer.extend_from_slice(header.as_bytes());
+            buffer.extend_from_slice(&[0; 16]);
+
+            assert_eq!(read_npy(&mut buffer.as_slice()).unwrap_err().kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_npz_roundtrip() {
+        let density = Grid::from_rows(vec![vec![1, 2], vec![3, 4]]);
+        let traffic = Grid::from_rows(vec![vec![0, 0], vec![7, 0]]);
+
+        let mut buffer = io::Cursor::new(Vec::<u8>::new());
+        write_npz(&mut buffer, &[("density", &density), ("traffic", &traffic)]).unwrap();
+
+        assert_eq!(read_npz(&mut buffer, Some("traffic")).unwrap().to_grid(), traffic);
+        assert_eq!(read_npz(&mut buffer, Some("density")).unwrap().to_grid(), density);
+        assert!(read_npz(&mut buffer, None).is_err());
+    }
+}
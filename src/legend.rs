@@ -0,0 +1 @@
+pub mod legend;
\ No newline at end of file
@@ -0,0 +1,169 @@
+#[cfg(test)]
+#[path = "tests.rs"]
+mod tests;
+
+use std::fs::File;
+use image::io::Reader as ImageReader;
+use image::{GenericImageView, DynamicImage};
+use hashbrown::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::grid::grid::Grid;
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct LegendEntry {
+    pub color: [u8; 4],
+    pub value: i32
+}
+
+// Maps the colors of a categorical density PNG to density values.
+// A pixel matches the closest entry (euclidean distance in RGBA) if it is not further than `tolerance`.
+// Pixels of the `nodata` color are always mapped to its value.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Legend {
+    pub entries: Vec<LegendEntry>,
+    #[serde(default)]
+    pub tolerance: f64,
+    #[serde(default)]
+    pub nodata: Option<LegendEntry>
+}
+
+impl Legend {
+    pub fn value_of(&self, color: &[u8; 4]) -> Option<i32> {
+        if let Some(nodata) = &self.nodata {
+            if &nodata.color == color {
+                return Some(nodata.value)
+            }
+        }
+
+        let mut best: Option<(f64, i32)> = None;
+
+        for entry in &self.entries {
+            let distance = color_distance(&entry.color, color);
+
+            if distance <= self.tolerance && best.is_none_or(|(d, _)| distance < d) {
+                best = Some((distance, entry.value));
+            }
+        }
+
+        best.map(|(_, value)| value)
+    }
+}
+
+// Maps the raw value of a grayscale (8 or 16 bit) pixel linearly to a density:
+// `value * scale + offset`, or `log_base^(value * scale + offset)` for log-scaled rasters.
+// Pixels equal to `nodata` get density 0.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct LinearScale {
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+    #[serde(default)]
+    pub log_base: Option<f64>,
+    #[serde(default)]
+    pub nodata: Option<u16>
+}
+
+impl LinearScale {
+    pub fn value_of(&self, raw: u16) -> i32 {
+        if self.nodata == Some(raw) {
+            return 0
+        }
+
+        let value = raw as f64 * self.scale + self.offset;
+
+        match self.log_base {
+            Some(base) => base.powf(value).round() as i32,
+            None => value.round() as i32
+        }
+    }
+}
+
+// How pixels of a density image are turned into densities.
+// A JSON file with `entries` is read as a palette legend, a file with `scale` as a linear scale.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DensityEncoding {
+    Palette(Legend),
+    Scaled(LinearScale)
+}
+
+impl DensityEncoding {
+    pub fn from_file(filename: &str) -> Self {
+        let file = File::open(filename).unwrap_or_else(|e| panic!("Unable to open legend {}: {}", filename, e));
+
+        serde_json::from_reader(file).unwrap_or_else(|e| panic!("Unable to parse legend {}: {}", filename, e))
+    }
+}
+
+pub fn color_distance(c1: &[u8; 4], c2: &[u8; 4]) -> f64 {
+    c1.iter()
+        .zip(c2.iter())
+        .map(|(a, b)| (*a as f64 - *b as f64).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+// Returns all distinct colors of the image with their pixel counts, the most frequent first
+pub fn extract_colors(image: &str) -> Vec<([u8; 4], usize)> {
+    let img = ImageReader::open(image).unwrap().decode().unwrap();
+    let mut counts: HashMap<[u8; 4], usize> = HashMap::new();
+
+    for (_, _, px) in img.pixels() {
+        *counts.entry(px.0).or_insert(0) += 1;
+    }
+
+    let mut colors: Vec<([u8; 4], usize)> = counts.into_iter().collect();
+    colors.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    colors
+}
+
+// Decodes a density image, row by row
+pub fn load_map_from_image(image: &str, encoding: &DensityEncoding) -> Grid<i32> {
+    let img = ImageReader::open(image).unwrap().decode().unwrap();
+
+    match encoding {
+        DensityEncoding::Palette(legend) => load_palette_map(&img, legend),
+        DensityEncoding::Scaled(scale) => load_scaled_map(&img, scale)
+    }
+}
+
+fn load_palette_map(img: &DynamicImage, legend: &Legend) -> Grid<i32> {
+    let mut map: Vec<Vec<i32>> = vec![];
+
+    for y in 0..img.height() {
+        let mut line: Vec<i32> = vec![];
+
+        for x in 0..img.width() {
+            let px = img.get_pixel(x, y);
+            let risk = legend.value_of(&px.0)
+                .unwrap_or_else(|| panic!("The color {:?} of pixel ({}, {}) is not in the legend", px.0, x, y));
+
+            line.push(risk);
+        }
+
+        map.push(line);
+    }
+
+    Grid::from_rows(map)
+}
+
+fn load_scaled_map(img: &DynamicImage, scale: &LinearScale) -> Grid<i32> {
+    // The raw pixel values are needed, to_luma16() would rescale 8-bit images
+    let raw_value = |x: u32, y: u32| -> u16 {
+        match img {
+            DynamicImage::ImageLuma8(img) => img.get_pixel(x, y).0[0] as u16,
+            DynamicImage::ImageLumaA8(img) => img.get_pixel(x, y).0[0] as u16,
+            DynamicImage::ImageLuma16(img) => img.get_pixel(x, y).0[0],
+            DynamicImage::ImageLumaA16(img) => img.get_pixel(x, y).0[0],
+            _ => panic!("A scaled density map must be a grayscale image")
+        }
+    };
+
+    let values = (0..img.height())
+        .flat_map(|y| (0..img.width()).map(move |x| (x, y)))
+        .map(|(x, y)| scale.value_of(raw_value(x, y)))
+        .collect();
+
+    Grid::from_vec(img.height() as usize, img.width() as usize, values)
+}
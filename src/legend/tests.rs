@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod tests {
+    use super::super::{*};
+
+    fn nk_legend(tolerance: f64) -> Legend {
+        Legend{
+            entries: vec![
+                LegendEntry{color: [255, 255, 255, 255], value: 1},
+                LegendEntry{color: [214, 214, 214, 255], value: 4},
+                LegendEntry{color: [27, 0, 31, 255], value: 1000},
+            ],
+            tolerance,
+            nodata: Some(LegendEntry{color: [0, 0, 0, 0], value: 0})
+        }
+    }
+
+    #[test]
+    fn test_exact_match() {
+        let legend = nk_legend(0.0);
+
+        assert_eq!(legend.value_of(&[255, 255, 255, 255]), Some(1));
+        assert_eq!(legend.value_of(&[214, 214, 214, 255]), Some(4));
+        assert_eq!(legend.value_of(&[27, 0, 31, 255]), Some(1000));
+        assert_eq!(legend.value_of(&[250, 255, 255, 255]), None);
+    }
+
+    #[test]
+    fn test_nearest_within_tolerance() {
+        let legend = nk_legend(10.0);
+
+        assert_eq!(legend.value_of(&[250, 255, 255, 255]), Some(1));
+        assert_eq!(legend.value_of(&[220, 210, 214, 255]), Some(4));
+        assert_eq!(legend.value_of(&[100, 100, 100, 255]), None);
+    }
+
+    #[test]
+    fn test_nodata() {
+        let legend = nk_legend(10.0);
+
+        assert_eq!(legend.value_of(&[0, 0, 0, 0]), Some(0));
+        assert_eq!(legend.value_of(&[0, 0, 0, 255]), None);
+    }
+
+    #[test]
+    fn test_parse_legend() {
+        let legend: Legend = serde_json::from_str(r#"{"entries": [{"color": [255, 255, 255, 255], "value": 1}]}"#).unwrap();
+
+        assert_eq!(legend, Legend{entries: vec![LegendEntry{color: [255, 255, 255, 255], value: 1}], tolerance: 0.0, nodata: None});
+    }
+
+    #[test]
+    fn test_linear_scale() {
+        let scale = LinearScale{scale: 0.5, offset: 2.0, log_base: None, nodata: Some(65535)};
+
+        assert_eq!(scale.value_of(0), 2);
+        assert_eq!(scale.value_of(10), 7);
+        assert_eq!(scale.value_of(65535), 0);
+    }
+
+    #[test]
+    fn test_log_scale() {
+        let scale = LinearScale{scale: 0.01, offset: 0.0, log_base: Some(10.0), nodata: None};
+
+        assert_eq!(scale.value_of(0), 1);
+        assert_eq!(scale.value_of(100), 10);
+        assert_eq!(scale.value_of(300), 1000);
+    }
+
+    #[test]
+    fn test_parse_encoding() {
+        let palette: DensityEncoding = serde_json::from_str(r#"{"entries": [{"color": [255, 255, 255, 255], "value": 1}], "tolerance": 5.0}"#).unwrap();
+        let scaled: DensityEncoding = serde_json::from_str(r#"{"scale": 2.0, "offset": 1.0}"#).unwrap();
+
+        assert!(matches!(palette, DensityEncoding::Palette(Legend{tolerance, ..}) if tolerance == 5.0));
+        assert_eq!(scaled, DensityEncoding::Scaled(LinearScale{scale: 2.0, offset: 1.0, log_base: None, nodata: None}));
+    }
+}
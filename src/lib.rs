@@ -0,0 +1,14 @@
+pub mod risks;
+pub mod air_risks;
+pub mod bicriteria_dijkstra;
+pub mod legend;
+pub mod grid_io;
+pub mod grid;
+pub mod air_traffic;
+pub mod zones;
+pub mod waypoint_graph;
+pub mod multires;
+pub mod search_backend;
+
+#[cfg(test)]
+mod test_maps;
\ No newline at end of file
@@ -1,62 +1,166 @@
 use std::fs::File;
-use crate::risks::risks::{RiskMap, Coord};
-use image::io::Reader as ImageReader;
-use image::GenericImageView;
-use std::collections::HashMap;
+use ground_risk::risks::risks::{RiskMap, Coord, Neighbourhood, NeighbourhoodShape};
 use std::borrow::Borrow;
-use crate::bicriteria_dijkstra::bicriteria_dijkstra::{BicriteriaDijkstraInstance, Path};
+use ground_risk::bicriteria_dijkstra::bicriteria_dijkstra::{BicriteriaDijkstraInstance, Leg, Path, RouteConstraint, TurnConstraint};
 use std::time::Instant;
-use crate::air_risks::air_risks::AirRiskInstance;
+use ground_risk::air_risks::air_risks::{AirRiskInstance, AltitudeBand, AltitudeProfile, EncounterModel};
 use serde::{Serialize, Deserialize};
 use std::fs;
+use std::env;
+use ground_risk::legend::legend::{DensityEncoding, extract_colors, load_map_from_image};
+use ground_risk::grid_io::grid_io::{load_grid, save_grid, save_values, extension};
+use ground_risk::grid::grid::Grid;
+use ground_risk::air_traffic::air_traffic::{Georeference, SpaceTimeOccupancy, read_tracks, build_air_risk_map, build_layered_air_risk_map};
+use ground_risk::waypoint_graph::waypoint_graph::{WaypointGraph, lattice_waypoints, boundary_waypoints};
+use ground_risk::multires::multires::{pyramid, coarse_instance, refine, WeightLowerBound};
+use ground_risk::search_backend::search_backend::{LabelStorage, QueueKind};
+use ground_risk::zones::zones::{ZoneMap, PenaltyMap, read_zones, read_penalty_zones};
 
-mod risks;
-mod air_risks;
-mod bicriteria_dijkstra;
+const ORIGIN: Coord<i16> = Coord{x: 517, y: 412};
+const DESTINATION: Coord<i16> = Coord{x: 765, y: 600};
 
 fn main() {
-    let mut colors: HashMap<&[u8; 4], i32> = HashMap::new();
-    colors.insert(&[255, 255, 255, 255], 1);
-    colors.insert(&[214, 214, 214, 255], 4);
-    colors.insert(&[180, 209, 82, 255], 19);
-    colors.insert(&[183, 103, 26, 255], 199);
-    colors.insert(&[109, 0, 65, 255], 499);
-    colors.insert(&[27, 0, 31, 255], 1000);
+    let mut args: Vec<String> = env::args().collect();
 
+    let transpose = args.iter().any(|a| a == "--transpose");
+    args.retain(|a| a != "--transpose");
 
-    let total_time = 4*7*24;
-    let map = load_map_from_image("./data/density_fixed_scaled.png", &colors);
-    let air_risk_instance = load_air_risk_map("./data/map.json", total_time);
+    let primitive_only = args.iter().any(|a| a == "--primitive");
+    args.retain(|a| a != "--primitive");
 
-    assert_eq!(map[0].len(), air_risk_instance.map.len());
-    assert_eq!(map.len(), air_risk_instance.map[0].len());
+    let bidirectional = args.iter().any(|a| a == "--bidirectional");
+    args.retain(|a| a != "--bidirectional");
 
-    let risk_map = RiskMap{
-        map: map,
-        m_per_pixel: 1000.0/(131.0/2.0),
-        offset: 25
+    let hull_bounds = args.iter().any(|a| a == "--hull-bounds");
+    args.retain(|a| a != "--hull-bounds");
+
+    let air_buffer_m: Option<f64> = take_option(&mut args, "--air-buffer").map(|v| v.parse().expect("air buffer must be a number"));
+    let smoothing_tolerance: Option<f64> = take_option(&mut args, "--smooth").map(|v| v.parse().expect("smoothing tolerance must be a number"));
+    let max_turn_deg: Option<f64> = take_option(&mut args, "--max-turn").map(|v| v.parse().expect("maximum turn must be a number"));
+    let turn_penalty: f64 = take_option(&mut args, "--turn-penalty").map_or(0.0, |v| v.parse().expect("turn penalty must be a number"));
+    let turn_constraint = if max_turn_deg.is_some() || turn_penalty != 0.0 {
+        Some(TurnConstraint{ max_turn_deg: max_turn_deg.unwrap_or(180.0), penalty_m_per_rad: turn_penalty })
+    } else {
+        None
+    };
+
+    let neighbourhood = parse_neighbourhood(take_option(&mut args, "--neighbourhood").as_deref().unwrap_or("square"), primitive_only);
+    let search_backend = parse_search_backend(take_option(&mut args, "--labels").as_deref().unwrap_or("dense"),
+                                              take_option(&mut args, "--queue").as_deref().unwrap_or("binary"));
+
+    let options = RouteOptions{
+        air_buffer_m,
+        neighbourhood,
+        bidirectional,
+        hull_bounds,
+        search_backend,
+        turn_constraint,
+        smoothing_tolerance,
+        waypoints: take_option(&mut args, "--waypoints").map(|v| parse_waypoints(&v)),
+        coarse: take_option(&mut args, "--coarse").map(|v| parse_coarse(&v)),
+        zone_files: ZoneFiles{
+            no_fly: take_option(&mut args, "--no-fly"),
+            penalty: take_option(&mut args, "--penalty"),
+            georef: take_option(&mut args, "--georef")
+        }
     };
 
+    match args.get(1).map(|s| s.as_str()) {
+        None => compute_routes(&options),
+        Some("constrained") if args.len() == 5 => compute_constrained_route(parse_constraint(&args[2], &args[3]), &args[4], &options),
+        Some("legend") if args.get(2).map(|s| s.as_str()) == Some("extract") && args.len() == 4 => print_legend_template(&args[3]),
+        Some("convert") if args.len() == 4 || args.len() == 5 => convert_grid(&args[2], &args[3], args.get(4), transpose),
+        Some("benchmark") if args.len() == 3 => benchmark_search_backends(args[2].parse().expect("alpha must be a number"), &options),
+        Some("labels") if args.len() == 4 => export_labels(args[2].parse().expect("alpha must be a number"), &args[3], &options.zone_files),
+        Some("air-risk-by-time") if args.len() == 7 => print_air_risk_by_time(&args[2], &args[3], &args[4],
+                                                                              args[5].parse().expect("speed must be a number"),
+                                                                              args[6].parse().expect("bin size must be a number")),
+        Some("build-air-risk") if args.len() == 7 => build_air_risk(&args[2], &args[3],
+                                                                   &AltitudeBand{min_m: args[4].parse().expect("altitude must be a number"),
+                                                                                 max_m: args[5].parse().expect("altitude must be a number")},
+                                                                   &args[6]),
+        Some("layered-air-risk") if args.len() == 7 => print_layered_air_risk(&args[2], &args[3], &args[4], &parse_bands(&args[5]),
+                                                                              &parse_list(&args[6])),
+        Some("encounters") if args.len() == 6 => print_expected_collisions(&args[2], &args[3], args[4].parse().expect("total time must be an integer"),
+                                                                          &args[5], transpose),
+        _ => {
+            eprintln!("Usage:");
+            eprintln!("  {} [--air-buffer=<m>] [--no-fly=<zones.json>] [--penalty=<zones.json>] [--georef=<georeference.json>]", args[0]);
+            eprintln!("      [--max-turn=<deg>] [--turn-penalty=<m per rad>] [--smooth=<tolerance>] [--neighbourhood=<mode>] [--primitive]");
+            eprintln!("      [--waypoints=<lattice|boundaries>:<spacing>] [--coarse=<factor>[:<levels>]] [--bidirectional] [--hull-bounds]");
+            eprintln!("      [--labels=<hash|dense>] [--queue=<binary|radix>]");
+            eprintln!("      compute the Pareto routes for the bundled map");
+            eprintln!("      --air-buffer averages the air risk over a corridor of the given half-width instead of the route line");
+            eprintln!("      --no-fly forbids pixel-space or GeoJSON zones, GeoJSON needs --georef to be placed on the map");
+            eprintln!("      --penalty multiplies the ground risk within zones by their `factor`");
+            eprintln!("      --max-turn limits the heading change between legs, --turn-penalty adds a cost to every turn");
+            eprintln!("      --smooth merges legs into straight ones with at most (1 + tolerance) times their ground risk");
+            eprintln!("      --neighbourhood is square (default), circle, 8, 16 or 32; --primitive leaves out moves repeating shorter ones");
+            eprintln!("      --waypoints searches a sparse graph of waypoints on a lattice or at density boundaries instead of the grid");
+            eprintln!("      --bidirectional searches every route from both of its ends");
+            eprintln!("      --hull-bounds skips cells which cannot improve on the two routes a new route is searched between");
+            eprintln!("      --labels and --queue select the data structures of the search, dense labels and a binary queue by default");
+            eprintln!("      --coarse computes the routes on the map downsampled levels (1 by default) times by the factor,");
+            eprintln!("      then refines them level by level in a corridor");
+            eprintln!("  {} legend extract <image>    list all distinct colors of an image as a legend template", args[0]);
+            eprintln!("  {} convert <input> <output> [legend] [--transpose]", args[0]);
+            eprintln!("      convert a grid between .png (needs a legend), .json, .npy, .npz and the binary .grid format");
+            eprintln!("      --transpose swaps rows and columns, the output has rows along y and columns along x");
+            eprintln!("  {} constrained <max-length|max-risk> <bound> <output.json> [route options as above]", args[0]);
+            eprintln!("      the least risky route within a length in metres, or the shortest route within a ground risk");
+            eprintln!("  {} benchmark <alpha> [route options as above]", args[0]);
+            eprintln!("      time the search of one route with every combination of --labels and --queue");
+            eprintln!("  {} labels <alpha> <output> [--no-fly=<zones.json>] [--penalty=<zones.json>] [--georef=<georeference.json>]", args[0]);
+            eprintln!("      save the one-to-all labels from the origin as .npy or .npz");
+            eprintln!("  {} air-risk-by-time <routes.json> <tracks.csv> <georeference.json> <speed_mps> <bin_s>", args[0]);
+            eprintln!("      air risk of the computed routes for departures at every time bin of the day");
+            eprintln!("  {} build-air-risk <tracks.csv> <georeference.json> <min_alt_m> <max_alt_m> <output>", args[0]);
+            eprintln!("      rasterise recorded tracks within the altitude band onto the grid of the density map");
+            eprintln!("  {} layered-air-risk <routes.json> <tracks.csv> <georeference.json> <bands> <altitudes>", args[0]);
+            eprintln!("      air risk of the computed routes at cruise altitudes, e.g. bands 0-90,90-150,150-500 and altitudes 60,120");
+            eprintln!("  {} encounters <routes.json> <air_risk_grid> <total_time_s> <model.json> [--transpose]", args[0]);
+            eprintln!("      expected mid-air collisions per flight of the computed routes (see data/encounter_model.json)");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn load_risk_map() -> RiskMap {
+    let encoding = DensityEncoding::from_file("./data/legend_nk.json");
+    let map = load_density_map("./data/density_fixed_scaled.png", &encoding, false);
+
+    RiskMap{
+        map,
+        m_per_pixel: 1000.0/(131.0/2.0),
+        offset: 25,
+        no_fly_zones: None,
+        penalty_zones: None
+    }
+}
+
+fn compute_routes(options: &RouteOptions) {
+    let total_time = 4*7*24;
+    let mut risk_map = load_risk_map();
+    options.zone_files.apply(&mut risk_map);
+    let air_risk_instance = load_bundled_air_risk_map(&risk_map, total_time, options.air_buffer_m);
+
     // let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 500, y: 500}, Coord{x: 600, y: 600}, 5, 150.0);
-    let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 517, y: 412}, Coord{x: 765, y: 600}, 5, 150.0);
+    let graph = options.waypoint_graph(&risk_map);
+    let inst = routing_instance(&risk_map, options, graph.as_ref());
 
     let start = Instant::now();
 
-    let paths = inst.compute_pareto_apx_paths();
+    let paths = match options.coarse {
+        Some((factor, levels)) => coarse_to_fine_paths(&inst, factor, levels),
+        None => inst.compute_pareto_apx_paths()
+    };
 
     println!("{:?}", paths);
 
     let mut res_routes = vec![];
 
     for path in paths {
-        let air_risk = air_risk_instance.compute_air_risk(&path);
-        res_routes.push(HFRMPath{
-            route: path.path,
-            air_risk: air_risk,
-            ground_risk: path.risk as f64,
-            length_m: path.length_m,
-            alpha: path.alpha
-        })
+        res_routes.push(to_hfrm_path(&inst, &air_risk_instance, options.smoothed(&inst, path)))
     }
 
     let duration = start.elapsed();
@@ -66,30 +170,407 @@ fn main() {
     save_paths_to_json("./results/res_nk.json", &res_routes);
 }
 
-fn load_map_from_image(image: &str, colors: &HashMap<&[u8; 4], i32>) -> Vec<Vec<i32>> {
-    let mut map: Vec<Vec<i32>> = vec![];
-    let img = ImageReader::open(image).unwrap().decode().unwrap();
+// The Pareto routes of the coarsest map of the pyramid, each refined down to full resolution within 2 coarse
+// pixels of the route of the level above
+fn coarse_to_fine_paths(inst: &BicriteriaDijkstraInstance, factor: i16, levels: usize) -> Vec<Path> {
+    let maps = pyramid(inst.risk_map, factor, levels);
+    let coarse = coarse_instance(inst, maps.last().unwrap(), factor.pow(levels as u32));
+    let lower_bound = WeightLowerBound::new(inst);
+
+    coarse.compute_pareto_apx_paths().into_iter()
+        .map(|coarse_path| {
+            let route = refine(inst, &maps, coarse_path, factor, 2, &lower_bound);
+            println!("alpha={}: coarse risk {}, refined risk {}, weight {} (lower bound {}, at most {:.2}% above the optimum)",
+                     route.path.alpha, route.coarse_path.risk, route.path.risk, route.path.linear_combination_weight,
+                     route.lower_bound, route.optimality_gap * 100.0);
+            route.path
+        })
+        .collect()
+}
+
+fn compute_constrained_route(constraint: RouteConstraint, output: &str, options: &RouteOptions) {
+    let total_time = 4*7*24;
+    let mut risk_map = load_risk_map();
+    options.zone_files.apply(&mut risk_map);
+    let air_risk_instance = load_bundled_air_risk_map(&risk_map, total_time, options.air_buffer_m);
+
+    let graph = options.waypoint_graph(&risk_map);
+    let inst = routing_instance(&risk_map, options, graph.as_ref());
+
+    match inst.compute_constrained_path(constraint) {
+        Ok(path) => {
+            println!("risk: {}, length: {} m", path.risk, path.length_m);
+            save_paths_to_json(output, &vec![to_hfrm_path(&inst, &air_risk_instance, options.smoothed(&inst, path))]);
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+fn routing_instance<'a>(risk_map: &'a RiskMap, options: &RouteOptions, graph: Option<&'a WaypointGraph>) -> BicriteriaDijkstraInstance<'a> {
+    let (neighbourhood, search_limit) = options.neighbourhood;
+    let mut inst = BicriteriaDijkstraInstance::new(risk_map, ORIGIN, DESTINATION, search_limit, 150.0)
+        .with_neighbourhood(neighbourhood)
+        .with_search_backend(options.search_backend.0, options.search_backend.1)
+        .with_progress();
+
+    if let Some(graph) = graph {
+        inst = inst.with_waypoint_graph(graph);
+    }
+
+    if options.bidirectional {
+        inst = inst.with_bidirectional_search();
+    }
+
+    if options.hull_bounds {
+        inst = inst.with_hull_bounds();
+    }
+
+    match options.turn_constraint {
+        Some(turns) => inst.with_turn_constraint(turns.max_turn_deg, turns.penalty_m_per_rad),
+        None => inst
+    }
+}
+
+// The neighbourhood with its search limit
+fn parse_neighbourhood(mode: &str, primitive_only: bool) -> (Neighbourhood, i16) {
+    match mode {
+        "square" => (Neighbourhood{ shape: NeighbourhoodShape::Square, primitive_only }, 5),
+        "circle" => (Neighbourhood{ shape: NeighbourhoodShape::Circle, primitive_only }, 5),
+        k => k.parse().ok()
+            .and_then(Neighbourhood::connected)
+            .unwrap_or_else(|| panic!("Unknown neighbourhood {}, expected square, circle, 8, 16 or 32", mode))
+    }
+}
+
+fn parse_search_backend(labels: &str, queue: &str) -> (LabelStorage, QueueKind) {
+    let label_storage = match labels {
+        "hash" => LabelStorage::Hash,
+        "dense" => LabelStorage::Dense,
+        _ => panic!("Unknown label storage {}, expected hash or dense", labels)
+    };
+    let queue = match queue {
+        "binary" => QueueKind::Binary,
+        "radix" => QueueKind::Radix,
+        _ => panic!("Unknown queue {}, expected binary or radix", queue)
+    };
+
+    (label_storage, queue)
+}
+
+// <factor>[:<levels>]
+fn parse_coarse(value: &str) -> (i16, usize) {
+    let (factor, levels) = value.split_once(':').unwrap_or((value, "1"));
+    let levels = levels.parse().expect("coarse levels must be an integer");
+    assert!(levels > 0, "coarse levels must be at least 1");
+
+    (factor.parse().expect("coarse factor must be an integer"), levels)
+}
+
+// <lattice|boundaries>:<spacing>
+fn parse_waypoints(value: &str) -> (String, i16) {
+    let (kind, spacing) = value.split_once(':').expect("waypoints must be given as <lattice|boundaries>:<spacing>");
+    let spacing = spacing.parse().expect("waypoint spacing must be an integer");
+    assert!(kind == "lattice" || kind == "boundaries", "Unknown waypoints {}, expected lattice or boundaries", kind);
+    assert!(spacing > 0, "waypoint spacing must be at least 1 pixel");
+
+    (kind.to_string(), spacing)
+}
+
+fn parse_constraint(kind: &str, bound: &str) -> RouteConstraint {
+    match kind {
+        "max-length" => RouteConstraint::MaxLength(bound.parse().expect("the maximum length must be a number")),
+        "max-risk" => RouteConstraint::MaxRisk(bound.parse().expect("the maximum risk must be an integer")),
+        _ => panic!("Unknown constraint {}, expected max-length or max-risk", kind)
+    }
+}
+
+fn load_bundled_air_risk_map(risk_map: &RiskMap, total_time_s: i32, air_buffer_m: Option<f64>) -> AirRiskInstance {
+    // map.json is stored column by column
+    let mut air_risk_instance = load_air_risk_map("./data/map.json", total_time_s, true);
+
+    if let Some(buffer_m) = air_buffer_m {
+        air_risk_instance = air_risk_instance.with_lateral_buffer(buffer_m, risk_map.m_per_pixel);
+    }
+
+    check_same_shape(&risk_map.map, &air_risk_instance.map);
+
+    air_risk_instance
+}
+
+fn to_hfrm_path(inst: &BicriteriaDijkstraInstance, air_risk_instance: &AirRiskInstance, path: Path) -> HFRMPath {
+    let air_risk = air_risk_instance.compute_air_risk(&path);
+    let legs = inst.legs(&path, air_risk_instance);
+    let constraining_zones = inst.constraining_zones(&path);
+
+    HFRMPath{
+        route: path.path,
+        air_risk,
+        ground_risk: path.risk as f64,
+        length_m: path.length_m,
+        alpha: path.alpha,
+        legs,
+        constraining_zones
+    }
+}
+
+// Times a route search with every search backend on the bundled map
+fn benchmark_search_backends(alpha: f64, options: &RouteOptions) {
+    let mut risk_map = load_risk_map();
+    options.zone_files.apply(&mut risk_map);
+    let inst = routing_instance(&risk_map, options, None);
+
+    for label_storage in [LabelStorage::Hash, LabelStorage::Dense] {
+        for queue in [QueueKind::Binary, QueueKind::Radix] {
+            let backend = inst.clone().with_search_backend(label_storage, queue);
+
+            let start = Instant::now();
+            let path = backend.run_with_alpha(alpha);
+
+            println!("{:?} labels, {:?} queue: {:?} (weight {})", label_storage, queue, start.elapsed(), path.linear_combination_weight);
+        }
+    }
+}
+
+fn export_labels(alpha: f64, output: &str, zone_files: &ZoneFiles) {
+    let mut risk_map = load_risk_map();
+    zone_files.apply(&mut risk_map);
+    let inst = BicriteriaDijkstraInstance::new(&risk_map, ORIGIN, DESTINATION, 5, 150.0);
 
-    for y in 0..img.height() {
-        let mut line: Vec<i32> = vec![];
+    let start = Instant::now();
+    let labels = inst.one_to_all_labels(alpha);
+
+    save_values(output, "labels", &labels).unwrap_or_else(|e| panic!("Unable to write {}: {}", output, e));
+
+    println!("Time elapsed is: {:?}", start.elapsed());
+}
+
+// Removes `--name=value` from the arguments and returns the value
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let prefix = format!("{}=", name);
+    let value = args.iter().find_map(|a| a.strip_prefix(prefix.as_str()).map(|v| v.to_string()));
+    args.retain(|a| !a.starts_with(prefix.as_str()));
+
+    value
+}
+
+// Options of the route computations given on the command line
+struct RouteOptions {
+    air_buffer_m: Option<f64>,
+    // The neighbourhood with its search limit
+    neighbourhood: (Neighbourhood, i16),
+    bidirectional: bool,
+    hull_bounds: bool,
+    search_backend: (LabelStorage, QueueKind),
+    turn_constraint: Option<TurnConstraint>,
+    smoothing_tolerance: Option<f64>,
+    // lattice or boundaries, and the spacing in pixels
+    waypoints: Option<(String, i16)>,
+    // The downsampling factor and the number of levels
+    coarse: Option<(i16, usize)>,
+    zone_files: ZoneFiles
+}
+
+impl RouteOptions {
+    // The waypoint graph with edges of up to three times the spacing
+    fn waypoint_graph(&self, risk_map: &RiskMap) -> Option<WaypointGraph> {
+        let (kind, spacing) = self.waypoints.as_ref()?;
+        let spacing = *spacing;
+
+        let mut nodes = match kind.as_str() {
+            "lattice" => lattice_waypoints(risk_map, spacing),
+            _ => boundary_waypoints(risk_map, spacing)
+        };
+        nodes.push(ORIGIN);
+        nodes.push(DESTINATION);
+
+        let start = Instant::now();
+        let graph = WaypointGraph::new(risk_map, nodes, 3.0 * spacing as f64, 150.0);
+        println!("Waypoint graph with {} waypoints and {} edges built in {:?}", graph.waypoints.len(), graph.edges_count(), start.elapsed());
+
+        Some(graph)
+    }
+
+    fn smoothed(&self, inst: &BicriteriaDijkstraInstance, path: Path) -> Path {
+        match self.smoothing_tolerance {
+            Some(tolerance) => inst.smooth_path(&path, tolerance),
+            None => path
+        }
+    }
+}
+
+// No-fly and penalty zones given on the command line
+struct ZoneFiles {
+    no_fly: Option<String>,
+    penalty: Option<String>,
+    georef: Option<String>
+}
+
+impl ZoneFiles {
+    fn apply(&self, risk_map: &mut RiskMap) {
+        let georef: Option<Georeference> = self.georef.as_ref().map(|f| serde_json::from_reader(File::open(f).unwrap()).unwrap());
+        let (rows, cols) = (risk_map.map.rows(), risk_map.map.cols());
 
-        for x in 0..img.width() {
-            let px = img.get_pixel(x, y);
-            let risk: &i32 = colors.get(&px.0).unwrap();
+        if let Some(zones_file) = &self.no_fly {
+            let zones = read_zones(open_zones(zones_file), georef.as_ref())
+                .unwrap_or_else(|e| panic!("Unable to read zones {}: {}", zones_file, e));
+            risk_map.no_fly_zones = Some(ZoneMap::new(zones, rows, cols));
+        }
 
-            line.push(*risk);
+        if let Some(zones_file) = &self.penalty {
+            let zones = read_penalty_zones(open_zones(zones_file), georef.as_ref())
+                .unwrap_or_else(|e| panic!("Unable to read zones {}: {}", zones_file, e));
+            risk_map.penalty_zones = Some(PenaltyMap::new(&zones, rows, cols));
         }
+    }
+}
 
-        map.push(line);
+fn open_zones(zones_file: &str) -> File {
+    File::open(zones_file).unwrap_or_else(|e| panic!("Unable to open zones {}: {}", zones_file, e))
+}
+
+fn check_same_shape(density_map: &Grid<i32>, air_risk_map: &Grid<i32>) {
+    if !density_map.same_shape(air_risk_map) {
+        panic!("The density map has {} rows and {} columns but the air risk map has {} rows and {} columns{}",
+               density_map.rows(), density_map.cols(), air_risk_map.rows(), air_risk_map.cols(),
+               if density_map.same_shape(&air_risk_map.transposed()) { ", one of them has to be transposed" } else { "" });
     }
+}
+
+// Density maps are either images decoded with the encoding or already decoded grids.
+// Grids stored with rows along x have to be transposed.
+fn load_density_map(filename: &str, encoding: &DensityEncoding, transpose: bool) -> Grid<i32> {
+    let map = if extension(filename) == "png" {
+        load_map_from_image(filename, encoding)
+    } else {
+        load_grid(filename).unwrap_or_else(|e| panic!("Unable to load {}: {}", filename, e))
+    };
 
-    return map
+    if transpose { map.transposed() } else { map }
 }
 
-fn load_air_risk_map(map_filename: &str, total_time_s: i32) -> AirRiskInstance {
-    let mut air_risk_map = serde_json::from_reader(File::open(map_filename).unwrap()).unwrap();
+fn load_air_risk_map(map_filename: &str, total_time_s: i32, transpose: bool) -> AirRiskInstance {
+    let air_risk_map = load_grid(map_filename).unwrap_or_else(|e| panic!("Unable to load {}: {}", map_filename, e));
+
+    AirRiskInstance::new(if transpose { air_risk_map.transposed() } else { air_risk_map }, total_time_s)
+}
+
+fn convert_grid(input: &str, output: &str, legend: Option<&String>, transpose: bool) {
+    let start = Instant::now();
+
+    let grid = if extension(input) == "png" {
+        let legend = legend.expect("A legend is needed to convert an image");
+        load_density_map(input, &DensityEncoding::from_file(legend), transpose)
+    } else {
+        let grid = load_grid(input).unwrap_or_else(|e| panic!("Unable to load {}: {}", input, e));
+        if transpose { grid.transposed() } else { grid }
+    };
+
+    save_grid(output, &grid).unwrap_or_else(|e| panic!("Unable to write {}: {}", output, e));
+
+    println!("Converted {} to {} ({} rows x {} columns) in {:?}", input, output, grid.rows(), grid.cols(), start.elapsed());
+}
 
-    return AirRiskInstance::new(air_risk_map, total_time_s);
+fn print_legend_template(image: &str) {
+    let entries: Vec<String> = extract_colors(image).iter()
+        .map(|(color, pixels)| format!("    {{\"color\": {:?}, \"value\": 0, \"pixels\": {}}}", color, pixels))
+        .collect();
+
+    println!("{{\n  \"tolerance\": 0.0,\n  \"nodata\": null,\n  \"entries\": [\n{}\n  ]\n}}", entries.join(",\n"));
+}
+
+// Segments of a track further apart than this are treated as lost contact
+const MAX_TRACK_GAP_S: f64 = 60.0;
+
+fn print_air_risk_by_time(routes_file: &str, tracks_file: &str, georef_file: &str, speed_mps: f64, bin_s: f64) {
+    let routes: Vec<HFRMPath> = serde_json::from_reader(File::open(routes_file).unwrap()).unwrap();
+    let tracks = read_tracks(File::open(tracks_file).unwrap()).unwrap_or_else(|e| panic!("Unable to read {}: {}", tracks_file, e));
+    let georef: Georeference = serde_json::from_reader(File::open(georef_file).unwrap()).unwrap();
+    let risk_map = load_risk_map();
+
+    let occupancy = SpaceTimeOccupancy::from_tracks(&tracks, &georef, risk_map.map.rows(), risk_map.map.cols(), bin_s, Some(86400.0), MAX_TRACK_GAP_S);
+
+    println!("alpha\tdeparture_s\tair_risk");
+
+    for route in &routes {
+        // Routes are stored from the destination to the origin
+        let waypoints: Vec<Coord<i16>> = route.route.iter().rev().cloned().collect();
+
+        for bin in 0..occupancy.bins_count() {
+            let departure_s = bin as f64 * bin_s;
+            println!("{}\t{}\t{}", route.alpha, departure_s, occupancy.route_air_risk(&waypoints, departure_s, speed_mps, georef.m_per_pixel));
+        }
+    }
+}
+
+fn build_air_risk(tracks_file: &str, georef_file: &str, band: &AltitudeBand, output: &str) {
+    let start = Instant::now();
+
+    let tracks = read_tracks(File::open(tracks_file).unwrap()).unwrap_or_else(|e| panic!("Unable to read {}: {}", tracks_file, e));
+    let georef: Georeference = serde_json::from_reader(File::open(georef_file).unwrap()).unwrap();
+    let risk_map = load_risk_map();
+
+    let instance = build_air_risk_map(&tracks, &georef, risk_map.map.rows(), risk_map.map.cols(), band, MAX_TRACK_GAP_S);
+
+    save_grid(output, &instance.map).unwrap_or_else(|e| panic!("Unable to write {}: {}", output, e));
+
+    println!("Rasterised {} tracks to {}, total_time_s = {}, in {:?}", tracks.len(), output, instance.total_time_s, start.elapsed());
+}
+
+fn print_layered_air_risk(routes_file: &str, tracks_file: &str, georef_file: &str, bands: &[AltitudeBand], altitudes_m: &[f64]) {
+    let routes: Vec<HFRMPath> = serde_json::from_reader(File::open(routes_file).unwrap()).unwrap();
+    let tracks = read_tracks(File::open(tracks_file).unwrap()).unwrap_or_else(|e| panic!("Unable to read {}: {}", tracks_file, e));
+    let georef: Georeference = serde_json::from_reader(File::open(georef_file).unwrap()).unwrap();
+    let risk_map = load_risk_map();
+
+    let instance = build_layered_air_risk_map(&tracks, &georef, risk_map.map.rows(), risk_map.map.cols(), bands, MAX_TRACK_GAP_S);
+
+    println!("alpha\taltitude_m\tair_risk");
+
+    for route in &routes {
+        let mut path = Path{path: route.route.clone(), linear_combination_weight: 0.0, risk: route.ground_risk as i32, length_m: route.length_m, alpha: route.alpha, altitude_profile: None};
+
+        for altitude_m in altitudes_m {
+            path.altitude_profile = Some(AltitudeProfile::constant(&path, *altitude_m));
+            println!("{}\t{}\t{}", route.alpha, altitude_m, instance.compute_air_risk(&path));
+        }
+    }
+}
+
+fn print_expected_collisions(routes_file: &str, air_risk_file: &str, total_time_s: i32, model_file: &str, transpose: bool) {
+    let routes: Vec<HFRMPath> = serde_json::from_reader(File::open(routes_file).unwrap()).unwrap();
+    let model: EncounterModel = serde_json::from_reader(File::open(model_file).unwrap()).unwrap();
+    let risk_map = load_risk_map();
+    let instance = load_air_risk_map(air_risk_file, total_time_s, transpose);
+
+    check_same_shape(&risk_map.map, &instance.map);
+
+    println!("alpha\tlength_m\texpected_collisions");
+
+    for route in &routes {
+        let path = Path{path: route.route.clone(), linear_combination_weight: 0.0, risk: route.ground_risk as i32, length_m: route.length_m, alpha: route.alpha, altitude_profile: None};
+
+        println!("{}\t{}\t{}", route.alpha, route.length_m, instance.expected_collisions(&path, &model, risk_map.m_per_pixel));
+    }
+}
+
+fn parse_list(list: &str) -> Vec<f64> {
+    list.split(',').map(|v| v.trim().parse().unwrap_or_else(|_| panic!("{} is not a number", v))).collect()
+}
+
+// Parses bands given as "min-max,min-max,..."
+fn parse_bands(bands: &str) -> Vec<AltitudeBand> {
+    bands.split(',')
+        .map(|band| {
+            let limits: Vec<f64> = band.split('-').map(|v| v.trim().parse().unwrap_or_else(|_| panic!("{} is not an altitude band", band))).collect();
+            if limits.len() != 2 {
+                panic!("{} is not an altitude band", band);
+            }
+            AltitudeBand{min_m: limits[0], max_m: limits[1]}
+        })
+        .collect()
 }
 
 fn save_paths_to_json(filename: &str, paths: &Vec<HFRMPath>) {
@@ -98,11 +579,17 @@ fn save_paths_to_json(filename: &str, paths: &Vec<HFRMPath>) {
     // println!("{}", &j)
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct HFRMPath {
     route: Vec<Coord<i16>>,
     air_risk: f64,
     ground_risk: f64,
     length_m: f64,
     alpha: f64,
+    // From the origin to the destination, unlike `route`
+    #[serde(default)]
+    legs: Vec<Leg>,
+    // The no-fly zones next to the route
+    #[serde(default)]
+    constraining_zones: Vec<String>
 }
\ No newline at end of file
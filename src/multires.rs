@@ -0,0 +1 @@
+pub mod multires;
\ No newline at end of file
@@ -0,0 +1,313 @@
+#[cfg(test)]
+#[path = "tests.rs"]
+mod tests;
+
+use bresenham::Bresenham;
+use hashbrown::HashMap;
+use crate::risks::risks::{RiskMap, Coord, corridor_pixels};
+use crate::grid::grid::Grid;
+use crate::bicriteria_dijkstra::bicriteria_dijkstra::{BicriteriaDijkstraInstance, Path};
+use crate::search_backend::search_backend::{BinaryQueue, SearchQueue};
+
+// The pixels an edge from (0, 0) passes through with its length in each of them, and the number of pixels of its footprint
+type EdgeFootprint = (Vec<(Coord<i16>, f64)>, usize);
+
+// A route found coarse-to-fine. `optimality_gap` bounds how much heavier (relatively) the route may be
+// than the optimal one for its alpha, it is computed against `lower_bound` on the weight of any route.
+// It is infinite if the bound is 0 and the route is not.
+#[derive(Debug)]
+pub struct CoarseToFineRoute {
+    pub path: Path,
+    pub coarse_path: Path,
+    pub lower_bound: f64,
+    pub optimality_gap: f64
+}
+
+// The density map with every `factor` x `factor` block of pixels summed into one pixel, so that
+// the ground risk of a route stays about the same. A coarse pixel is in a no-fly zone if any of its
+// pixels is, and takes the largest penalty factor of its pixels.
+pub fn downsample(risk_map: &RiskMap, factor: i16) -> RiskMap {
+    let f = factor as usize;
+    let (rows, cols) = (risk_map.map.rows().div_ceil(f), risk_map.map.cols().div_ceil(f));
+    let mut map = Grid::new(rows, cols, 0);
+
+    for row in 0..risk_map.map.rows() {
+        for col in 0..risk_map.map.cols() {
+            map.set(row / f, col / f, map.get(row / f, col / f) + risk_map.map.get(row, col));
+        }
+    }
+
+    RiskMap{
+        map,
+        m_per_pixel: risk_map.m_per_pixel * factor as f64,
+        offset: (risk_map.offset + factor - 1) / factor,
+        no_fly_zones: risk_map.no_fly_zones.as_ref().map(|zones| zones.downsample(f)),
+        penalty_zones: risk_map.penalty_zones.as_ref().map(|zones| zones.downsample(f))
+    }
+}
+
+// The pixels of the full-resolution map covered by the coarse path, widened by `margin` coarse pixels
+pub fn corridor_of(coarse_path: &Path, factor: i16, margin: i16, rows: usize, cols: usize) -> Grid<bool> {
+    let mut corridor = Grid::new(rows, cols, false);
+    let mut coarse_cells: Vec<Coord<i16>> = coarse_path.path.windows(2)
+        .flat_map(|leg| Bresenham::new((leg[0].x as isize, leg[0].y as isize), (leg[1].x as isize, leg[1].y as isize)))
+        .map(|(x, y)| Coord{x: x as i16, y: y as i16})
+        .collect();
+    coarse_cells.extend(coarse_path.path.last());
+
+    for cell in coarse_cells {
+        let (x_from, y_from) = ((cell.x - margin) * factor, (cell.y - margin) * factor);
+        let (x_to, y_to) = ((cell.x + margin + 1) * factor, (cell.y + margin + 1) * factor);
+
+        for y in y_from.max(0)..y_to.min(rows as i16) {
+            for x in x_from.max(0)..x_to.min(cols as i16) {
+                corridor.set(y as usize, x as usize, true);
+            }
+        }
+    }
+
+    corridor
+}
+
+// A lower bound on the weight of any route of the instance for the alpha, the weight of the lightest route
+// of the same graph with edges which cannot weigh more than the real ones. Turn constraints, no-fly zones
+// and corridors only make the real routes heavier.
+//
+// The footprint of an edge is a parallelogram of the length of the edge and twice `ceil(r_m / m_per_pixel)`
+// pixels wide, every pixel it overlaps counts whole. Each of these pixels lies within the half-width plus
+// sqrt(2) pixels of a pixel the edge passes through, so the risk of the edge is at least
+// - the sum over the pixels it passes through of the length in the pixel x the width x the lowest density
+//   that close to the pixel, its cross-section there covering at least that much, and
+// - the number of pixels of the footprint x the lowest of these densities.
+pub fn weight_lower_bound(inst: &BicriteriaDijkstraInstance, alpha: f64) -> f64 {
+    WeightLowerBound::new(inst).weight(alpha)
+}
+
+// The part of `weight_lower_bound` which does not depend on alpha, computed once for all the routes of an instance
+pub struct WeightLowerBound<'a> {
+    inst: &'a BicriteriaDijkstraInstance<'a>,
+    densities: Grid<f64>,
+    half_width: f64,
+    // Penalty factors below 1 lower the risk of a whole edge, after which it is rounded
+    scale: f64,
+    slack: f64
+}
+
+impl <'a> WeightLowerBound<'a> {
+    pub fn new(inst: &'a BicriteriaDijkstraInstance<'a>) -> Self {
+        let half_width = (inst.r_m / inst.risk_map.m_per_pixel).ceil();
+        let (scale, slack) = match inst.risk_map.penalty_zones.as_ref().and_then(|zones| zones.smallest_factor()) {
+            Some(factor) if factor < 1.0 => (factor, 0.5),
+            _ => (1.0, 0.0)
+        };
+
+        Self{ inst, densities: min_densities(inst.risk_map, half_width as usize + 2), half_width, scale, slack }
+    }
+
+    pub fn weight(&self, alpha: f64) -> f64 {
+        let (inst, risk_map, densities) = (self.inst, self.inst.risk_map, &self.densities);
+
+        // The footprint of an edge is the same wherever the edge starts
+        let mut edges: HashMap<(i16, i16), EdgeFootprint> = HashMap::new();
+        let mut relaxed_weight = |p: Coord<i16>, q: Coord<i16>| {
+            let (dx, dy) = (q.x - p.x, q.y - p.y);
+            let (pixels, footprint) = edges.entry((dx, dy)).or_insert_with(|| {
+                let footprint = corridor_pixels(Coord{x: 0, y: 0}, Coord{x: dx, y: dy}, inst.r_m, risk_map.m_per_pixel).len();
+                (pixel_lengths(dx, dy), footprint)
+            });
+
+            let mut area_risk = 0.0;
+            let mut least_density = f64::INFINITY;
+            for (offset, length_px) in pixels.iter() {
+                let c = Coord{x: p.x + offset.x, y: p.y + offset.y};
+                let density = if c.x >= 0 && c.y >= 0 && (c.x as usize) < densities.cols() && (c.y as usize) < densities.rows() {
+                    densities.at(c)
+                } else {
+                    0.0
+                };
+
+                area_risk += density * length_px * 2.0 * self.half_width;
+                least_density = least_density.min(density);
+            }
+
+            let risk = area_risk.max(least_density * *footprint as f64);
+            risk_map.length_m(p, q) + alpha * (risk * self.scale - self.slack).max(0.0)
+        };
+
+        // A* with the straight-line length, which no route is shorter than
+        let remaining_m = |p: Coord<i16>| (((inst.to.x - p.x) as f64).powi(2) + ((inst.to.y - p.y) as f64).powi(2)).sqrt() * risk_map.m_per_pixel;
+        let mut labels = Grid::new(risk_map.map.rows(), risk_map.map.cols(), f64::INFINITY);
+        let mut settled = Grid::new(risk_map.map.rows(), risk_map.map.cols(), false);
+        let mut queue = BinaryQueue::new();
+
+        labels.set(inst.from.y as usize, inst.from.x as usize, 0.0);
+        queue.push(inst.from, remaining_m(inst.from));
+
+        while let Some((node, _)) = queue.pop() {
+            if node == inst.to {
+                return labels.at(node)
+            }
+
+            settled.set(node.y as usize, node.x as usize, true);
+
+            let neighbours: Vec<Coord<i16>> = match inst.waypoint_graph {
+                Some(graph) => graph.edges_from(node).iter().map(|edge| edge.to).collect(),
+                None => risk_map.neighbours_in(node, inst.search_limit, inst.neighbourhood).collect()
+            };
+
+            for neighbour in neighbours {
+                if settled.at(neighbour) {
+                    continue;
+                }
+
+                let label = labels.at(node) + relaxed_weight(node, neighbour);
+
+                if label < labels.at(neighbour) {
+                    labels.set(neighbour.y as usize, neighbour.x as usize, label);
+                    queue.push(neighbour, label + remaining_m(neighbour));
+                }
+            }
+        }
+
+        f64::INFINITY
+    }
+}
+
+// How much heavier the route is than the lower bound, relatively
+fn optimality_gap(weight: f64, lower_bound: f64) -> f64 {
+    if weight <= lower_bound {
+        0.0
+    } else if lower_bound > 0.0 {
+        (weight - lower_bound) / lower_bound
+    } else {
+        f64::INFINITY
+    }
+}
+
+// The lowest density within `radius` pixels (along each axis) of every pixel. Only the pixels within the width
+// and height of the map count in `RiskMap::risk`, so the others, and those off the map, count as empty.
+fn min_densities(risk_map: &RiskMap, radius: usize) -> Grid<f64> {
+    let (rows, cols) = (risk_map.map.rows(), risk_map.map.cols());
+    let density = |row: usize, col: usize| if (row as i16) < risk_map.height() && (col as i16) < risk_map.width() {
+        risk_map.map.get(row, col).max(0) as f64
+    } else {
+        0.0
+    };
+
+    // The minimum over a square is separable
+    let window = |i: usize, len: usize| (i.saturating_sub(radius), i + radius, i >= radius && i + radius < len);
+    let mut row_minima = Grid::new(rows, cols, 0.0);
+    for row in 0..rows {
+        for col in 0..cols {
+            let (from, to, inside) = window(col, cols);
+            let minimum = (from..=to.min(cols - 1)).map(|c| density(row, c)).fold(f64::INFINITY, f64::min);
+            row_minima.set(row, col, if inside { minimum } else { 0.0 });
+        }
+    }
+
+    let mut densities = Grid::new(rows, cols, 0.0);
+    for row in 0..rows {
+        for col in 0..cols {
+            let (from, to, inside) = window(row, rows);
+            let minimum = (from..=to.min(rows - 1)).map(|r| row_minima.get(r, col)).fold(f64::INFINITY, f64::min);
+            densities.set(row, col, if inside { minimum } else { 0.0 });
+        }
+    }
+
+    densities
+}
+
+// The pixels the segment from (0, 0) to (dx, dy) passes through, with its length in pixels within each of them
+fn pixel_lengths(dx: i16, dy: i16) -> Vec<(Coord<i16>, f64)> {
+    let length_px = ((dx as f64).powi(2) + (dy as f64).powi(2)).sqrt();
+
+    // Where the segment crosses the borders of the pixels
+    let mut crossings = vec![0.0, 1.0];
+    for d in [dx, dy] {
+        for k in 0..d.unsigned_abs() {
+            crossings.push((k as f64 + 0.5) / d.unsigned_abs() as f64);
+        }
+    }
+    crossings.sort_by(|a: &f64, b| a.total_cmp(b));
+    crossings.dedup();
+
+    crossings.windows(2)
+        .map(|t| {
+            let middle = (t[0] + t[1]) / 2.0;
+            (Coord{x: (dx as f64 * middle).round() as i16, y: (dy as f64 * middle).round() as i16}, (t[1] - t[0]) * length_px)
+        })
+        .collect()
+}
+
+// The map downsampled `levels` times by `factor` (see `downsample`), from the finest to the coarsest
+pub fn pyramid(risk_map: &RiskMap, factor: i16, levels: usize) -> Vec<RiskMap> {
+    let mut maps: Vec<RiskMap> = vec![];
+
+    for _ in 0..levels {
+        let coarse = downsample(maps.last().unwrap_or(risk_map), factor);
+        maps.push(coarse);
+    }
+
+    maps
+}
+
+// The instance on the map downsampled by `factor` (see `downsample`), with the same search options
+pub fn coarse_instance<'a>(inst: &BicriteriaDijkstraInstance<'a>, coarse_map: &'a RiskMap, factor: i16) -> BicriteriaDijkstraInstance<'a> {
+    let mut coarse = inst.clone();
+    coarse.risk_map = coarse_map;
+    coarse.from = scaled(inst.from, factor);
+    coarse.to = scaled(inst.to, factor);
+    // Both are given in the pixels of the full-resolution map
+    coarse.waypoint_graph = None;
+    coarse.corridor = None;
+
+    coarse
+}
+
+// Refines a route of the coarsest map of the pyramid level by level, every time searching only within `margin`
+// coarse pixels of the route of the level above, down to the full-resolution map, with the same alpha.
+// The lower bound has to be of the same instance.
+pub fn refine(inst: &BicriteriaDijkstraInstance, pyramid: &[RiskMap], coarse_path: Path, factor: i16, margin: i16, lower_bound: &WeightLowerBound) -> CoarseToFineRoute {
+    let alpha = coarse_path.alpha;
+    let mut path: Option<Path> = None;
+
+    for level in (0..pyramid.len() - 1).rev() {
+        let above = path.as_ref().unwrap_or(&coarse_path);
+        let coarse = coarse_instance(inst, &pyramid[level], factor.pow(level as u32 + 1));
+        path = Some(search_in_corridor(&coarse, above, factor, margin, alpha));
+    }
+
+    let path = search_in_corridor(inst, path.as_ref().unwrap_or(&coarse_path), factor, margin, alpha);
+    let lower_bound = lower_bound.weight(alpha);
+    let optimality_gap = optimality_gap(path.linear_combination_weight, lower_bound);
+
+    CoarseToFineRoute{ path, coarse_path, lower_bound, optimality_gap }
+}
+
+// The route within `margin` pixels of the level above around the route found there. Where the corridor closes off the
+// destination, e.g. at a wall too thin to show on the level above, the margin is doubled until it covers the map.
+fn search_in_corridor(inst: &BicriteriaDijkstraInstance, above: &Path, factor: i16, margin: i16, alpha: f64) -> Path {
+    let (rows, cols) = (inst.risk_map.map.rows(), inst.risk_map.map.cols());
+    let mut margin = margin;
+
+    loop {
+        let corridor = corridor_of(above, factor, margin, rows, cols);
+
+        if corridor.values().iter().all(|inside| *inside) {
+            return inst.run_with_alpha(alpha)
+        }
+
+        let mut restricted = inst.clone();
+        restricted.corridor = Some(&corridor);
+
+        if let Some(path) = restricted.try_run_with_alpha(alpha) {
+            return path
+        }
+
+        margin = (margin * 2).max(1);
+    }
+}
+
+fn scaled(p: Coord<i16>, factor: i16) -> Coord<i16> {
+    Coord{x: p.x / factor, y: p.y / factor}
+}
@@ -0,0 +1,170 @@
+#[cfg(test)]
+mod tests {
+    use super::super::{*};
+    use crate::test_maps::test_maps::{risk_map, with_wall};
+    use crate::zones::zones::{Zone, ZoneMap};
+
+    #[test]
+    fn test_downsample() {
+        let map = risk_map(Grid::from_rows(vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]]));
+        let coarse = downsample(&map, 2);
+
+        assert_eq!(coarse.map.to_rows(), vec![vec![12, 9], vec![15, 9]]);
+        assert_eq!(coarse.m_per_pixel, 2.0);
+    }
+
+    #[test]
+    fn test_corridor() {
+        let coarse_path = Path{path: vec![Coord{x: 2, y: 0}, Coord{x: 0, y: 0}], linear_combination_weight: 0.0, risk: 0, length_m: 0.0, alpha: 0.0, altitude_profile: None};
+        let corridor = corridor_of(&coarse_path, 2, 0, 4, 8);
+
+        assert_eq!(corridor.row(0), &[true, true, true, true, true, true, false, false]);
+        assert_eq!(corridor.row(2), &[false; 8]);
+        assert_eq!(corridor_of(&coarse_path, 2, 1, 4, 8).row(3), &[true, true, true, true, true, true, true, true]);
+    }
+
+    #[test]
+    fn test_coarse_to_fine() {
+        let risk_map = with_wall(risk_map(Grid::new(24, 24, 1)), 0..18, 10..14, 10000);
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 4, y: 4}, Coord{x: 20, y: 4}, 2, 0.5);
+
+        let levels = pyramid(&risk_map, 2, 1);
+        let coarse = coarse_instance(&inst, &levels[0], 2);
+        let route = refine(&inst, &levels, coarse.run_with_alpha(1.0), 2, 1, &WeightLowerBound::new(&inst));
+        let optimal = inst.run_with_alpha(1.0);
+
+        assert_eq!((route.path.path[0], *route.path.path.last().unwrap()), (Coord{x: 20, y: 4}, Coord{x: 4, y: 4}));
+        assert!(route.path.risk < 10000);
+        assert!(route.lower_bound <= optimal.linear_combination_weight);
+
+        // The route is at most the reported gap heavier than the optimal one
+        let excess = (route.path.linear_combination_weight - optimal.linear_combination_weight) / optimal.linear_combination_weight;
+        assert!(excess >= -1e-9 && excess <= route.optimality_gap);
+    }
+
+    #[test]
+    fn test_pyramid() {
+        let risk_map = with_wall(risk_map(Grid::new(40, 40, 1)), 0..30, 18..22, 10000);
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 6, y: 6}, Coord{x: 34, y: 6}, 2, 0.5);
+
+        let levels = pyramid(&risk_map, 2, 2);
+        assert_eq!(levels.iter().map(|level| (level.map.rows(), level.m_per_pixel)).collect::<Vec<_>>(), vec![(20, 2.0), (10, 4.0)]);
+
+        let coarse = coarse_instance(&inst, &levels[1], 4);
+        let route = refine(&inst, &levels, coarse.run_with_alpha(1.0), 2, 1, &WeightLowerBound::new(&inst));
+        let optimal = inst.run_with_alpha(1.0);
+
+        assert_eq!((route.path.path[0], *route.path.path.last().unwrap()), (Coord{x: 34, y: 6}, Coord{x: 6, y: 6}));
+        assert!(route.path.risk < 10000);
+
+        let excess = (route.path.linear_combination_weight - optimal.linear_combination_weight) / optimal.linear_combination_weight;
+        assert!(excess >= -1e-9 && excess <= route.optimality_gap);
+    }
+
+    #[test]
+    fn test_coarse_to_fine_through_wall() {
+        // The coarse route goes straight through a no-fly wall, as if it was too thin for the coarse map.
+        // The way around the wall is more than 1 coarse pixel away from the route.
+        let mut risk_map = risk_map(Grid::new(24, 24, 1));
+        let wall = Zone{
+            name: "fence".to_string(),
+            polygon: vec![Coord{x: 9.5, y: -0.5}, Coord{x: 10.5, y: -0.5}, Coord{x: 10.5, y: 12.5}, Coord{x: 9.5, y: 12.5}],
+            holes: vec![]
+        };
+        risk_map.no_fly_zones = Some(ZoneMap::new(vec![wall], 24, 24));
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 4, y: 4}, Coord{x: 20, y: 4}, 2, 0.5);
+
+        let coarse_path = Path{path: vec![Coord{x: 10, y: 2}, Coord{x: 2, y: 2}], linear_combination_weight: 0.0, risk: 0, length_m: 0.0, alpha: 1.0, altitude_profile: None};
+        let mut corridor_only = inst.clone();
+        let corridor = corridor_of(&coarse_path, 2, 1, 24, 24);
+        corridor_only.corridor = Some(&corridor);
+        assert!(corridor_only.try_run_with_alpha(1.0).is_none());
+
+        let route = refine(&inst, &pyramid(&risk_map, 2, 1), coarse_path, 2, 1, &WeightLowerBound::new(&inst));
+
+        assert_eq!((route.path.path[0], *route.path.path.last().unwrap()), (Coord{x: 20, y: 4}, Coord{x: 4, y: 4}));
+        assert!(route.path.path.windows(2).all(|leg| risk_map.blocking_zone(leg[0], leg[1], 0.5).is_none()));
+        assert!(route.path.path.iter().any(|p| p.y > 12));
+    }
+
+    #[test]
+    fn test_optimality_gap() {
+        assert_eq!(optimality_gap(12.0, 10.0), 0.2);
+        assert_eq!(optimality_gap(0.0, 0.0), 0.0);
+        assert_eq!(optimality_gap(1.0, 0.0), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_pixel_lengths() {
+        let pixels = pixel_lengths(2, 1);
+
+        assert_eq!(pixels.iter().map(|(c, _)| *c).collect::<Vec<_>>(), vec![Coord{x: 0, y: 0}, Coord{x: 1, y: 0}, Coord{x: 1, y: 1}, Coord{x: 2, y: 1}]);
+        assert!((pixels.iter().map(|(_, length)| length).sum::<f64>() - 5.0f64.sqrt()).abs() < 1e-9);
+
+        // Through the corner between the pixels
+        assert_eq!(pixels_of(pixel_lengths(1, -1)), vec![Coord{x: 0, y: 0}, Coord{x: 1, y: -1}]);
+    }
+
+    fn pixels_of(pixels: Vec<(Coord<i16>, f64)>) -> Vec<Coord<i16>> {
+        pixels.into_iter().map(|(c, _)| c).collect()
+    }
+
+    #[test]
+    fn test_weight_lower_bound() {
+        let mut map = Grid::new(20, 20, 3);
+        for (i, value) in map.values().to_vec().iter().enumerate() {
+            map.set(i / 20, i % 20, value + (i * 7919 % 13) as i32);
+        }
+        let risk_map = with_wall(risk_map(map), 0..14, 9..11, 500);
+
+        for (from, to) in [(Coord{x: 3, y: 3}, Coord{x: 16, y: 4}), (Coord{x: 2, y: 17}, Coord{x: 17, y: 2})] {
+            for r_m in [0.5, 2.0] {
+                let inst = BicriteriaDijkstraInstance::new(&risk_map, from, to, 3, r_m);
+
+                for alpha in [0.0, 0.01, 1.0] {
+                    let (lower_bound, optimal) = (weight_lower_bound(&inst, alpha), inst.run_with_alpha(alpha).linear_combination_weight);
+
+                    assert!(lower_bound <= optimal + 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_weight_lower_bound_tightness() {
+        // Densities growing smoothly eastwards and southwards, as on real maps
+        let mut map = Grid::new(48, 48, 3);
+        for (i, value) in map.values().to_vec().iter().enumerate() {
+            map.set(i / 48, i % 48, value + (i % 48 / 4 + i / 48 / 6) as i32);
+        }
+        let risk_map = risk_map(map);
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 10, y: 12}, Coord{x: 37, y: 30}, 3, 2.0);
+
+        for (alpha, ratio) in [(0.01, 0.9), (0.1, 0.5)] {
+            let optimal = inst.run_with_alpha(alpha).linear_combination_weight;
+            let lower_bound = weight_lower_bound(&inst, alpha);
+
+            assert!(lower_bound <= optimal && lower_bound >= ratio * optimal);
+        }
+    }
+
+    #[test]
+    fn test_coarse_to_fine_around_no_fly_zone() {
+        let mut risk_map = risk_map(Grid::new(24, 24, 1));
+        let wall = Zone{
+            name: "prison".to_string(),
+            polygon: vec![Coord{x: 9.5, y: -0.5}, Coord{x: 13.5, y: -0.5}, Coord{x: 13.5, y: 17.5}, Coord{x: 9.5, y: 17.5}],
+            holes: vec![]
+        };
+        risk_map.no_fly_zones = Some(ZoneMap::new(vec![wall], 24, 24));
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 4, y: 4}, Coord{x: 20, y: 4}, 2, 0.5);
+
+        let levels = pyramid(&risk_map, 2, 1);
+        assert_eq!(levels[0].no_fly_zones.as_ref().unwrap().zone_at(Coord{x: 5, y: 3}), Some(0));
+
+        let route = refine(&inst, &levels, coarse_instance(&inst, &levels[0], 2).run_with_alpha(1.0), 2, 1, &WeightLowerBound::new(&inst));
+
+        assert_eq!((route.path.path[0], *route.path.path.last().unwrap()), (Coord{x: 20, y: 4}, Coord{x: 4, y: 4}));
+        assert!(route.path.path.windows(2).all(|leg| risk_map.blocking_zone(leg[0], leg[1], 0.5).is_none()));
+    }
+}
@@ -1,24 +1,32 @@
 #[cfg(test)]
 #[path = "tests.rs"]
 mod tests;
+#[cfg(test)]
 use itertools::Itertools;
 use core::cmp;
+#[cfg(test)]
 use ordered_float::OrderedFloat;
 use std::fmt::{Display, Formatter};
 use serde::{Serialize, Deserialize};
+use crate::grid::grid::Grid;
+use crate::zones::zones::{ZoneMap, PenaltyMap};
+use bresenham::Bresenham;
 
 pub struct RiskMap {
-    pub map: Vec<Vec<i32>>,
+    pub map: Grid<i32>,
     pub m_per_pixel: f64,
-    pub offset: i16
+    pub offset: i16,
+    pub no_fly_zones: Option<ZoneMap>,
+    pub penalty_zones: Option<PenaltyMap>
 }
 
-#[derive(Clone, Copy, PartialEq, Debug, Hash, Eq, Serialize)]
+#[derive(Clone, Copy, PartialEq, Debug, Hash, Eq, Serialize, Deserialize)]
 pub struct Coord <T> {
     pub x: T,
     pub y: T
 }
 
+#[cfg(test)]
 impl Coord<f64> {
     fn round_if_needed(&mut self) {
         // A dirty hack to avoid numerical problems
@@ -35,7 +43,7 @@ impl Coord<f64> {
 
 impl RiskMap {
     pub fn height(&self) -> i16 {
-        let height = self.map.len() as i16 - self.offset*2;
+        let height = self.map.rows() as i16 - self.offset*2;
         if height < 1 {
             panic!("The map is smaller than the offset");
         }
@@ -44,29 +52,38 @@ impl RiskMap {
     }
 
     pub fn width(&self) -> i16 {
-        let width = self.map.get(0).unwrap().len() as i16 - self.offset*2;
+        let width = self.map.cols() as i16 - self.offset*2;
         if width < 1 {
             panic!("The map is smaller than the offset");
         }
         return width as i16
     }
 
+    // The population within the parallelogram, multiplied by the largest factor of the penalty zones it overlaps
     fn parallelogram_risk(&self, origin_side: (Coord<f64>, Coord<f64>), destination_side: (Coord<f64>, Coord<f64>)) -> i32 {
         let mut pop = 0;
+        let mut factor: Option<f64> = None;
 
-        let rect = ParallelogramPixels::new(origin_side, destination_side);
+        let rect = Parallelogram::new(origin_side, destination_side);
 
-        for coord in rect.iter() {
+        for coord in rect.pixels() {
             if 0 <= coord.x && coord.x < self.width() && 0 <= coord.y && coord.y < self.height() {
                 pop = pop + self.risk_at(coord);
+
+                if let Some(zone_factor) = self.penalty_zones.as_ref().and_then(|zones| zones.factor_at(coord)) {
+                    factor = Some(factor.map_or(zone_factor, |f| f.max(zone_factor)));
+                }
             }
         }
 
-        return pop
+        match factor {
+            Some(factor) => (pop as f64 * factor).round() as i32,
+            None => pop
+        }
     }
 
     pub fn risk_at(&self, coord: Coord<i16>) -> i32 {
-        return self.map[coord.y as usize][coord.x as usize];
+        return self.map.at(coord);
     }
 
     fn parallelogram_from_two_points(p1: Coord<i16>, p2: Coord<i16>, r_m: f64, m_per_pixel: f64) -> ((Coord<f64>, Coord<f64>), (Coord<f64>, Coord<f64>)) {
@@ -104,8 +121,23 @@ impl RiskMap {
         return self.parallelogram_risk(orig_side, dest_side);
     }
 
+    // The first no-fly zone overlapped by the segment from p1 to p2 or by its footprint
+    pub fn blocking_zone(&self, p1: Coord<i16>, p2: Coord<i16>, r_m: f64) -> Option<usize> {
+        let zones = self.no_fly_zones.as_ref()?;
+        let line = Bresenham::new((p1.x as isize, p1.y as isize), (p2.x as isize, p2.y as isize))
+            .map(|(x, y)| Coord{x: x as i16, y: y as i16});
+
+        line.chain(std::iter::once(p2))
+            .chain(corridor_pixels(p1, p2, r_m, self.m_per_pixel))
+            .find_map(|coord| zones.zone_at(coord))
+    }
+
     pub fn neighbours_within(&self, p: Coord<i16>, search_limit: i16) -> NeighboursIter {
-        return NeighboursIter::new(self, search_limit, p);
+        NeighboursIter::new(self, search_limit, p, Neighbourhood::SQUARE)
+    }
+
+    pub fn neighbours_in(&self, p: Coord<i16>, search_limit: i16, neighbourhood: Neighbourhood) -> NeighboursIter<'_> {
+        NeighboursIter::new(self, search_limit, p, neighbourhood)
     }
 
     pub fn all_points_iterator(&self) -> AllPointsIter {
@@ -121,11 +153,158 @@ impl RiskMap {
     }
 }
 
+// Pixels of the corridor of half-width r_m around the segment, the footprint used by `RiskMap::risk`
+pub fn corridor_pixels(p1: Coord<i16>, p2: Coord<i16>, r_m: f64, m_per_pixel: f64) -> Vec<Coord<i16>> {
+    let (orig_side, dest_side) = RiskMap::parallelogram_from_two_points(p1, p2, r_m, m_per_pixel);
+
+    Parallelogram::new(orig_side, dest_side).pixels().collect()
+}
+
+// A parallelogram in pixel coordinates, where a pixel is the unit square centred on its coordinates.
+// Its pixels are the ones whose open square meets the closed parallelogram: the pixels it overlaps with
+// a positive area, or along a positive length when it is degenerated into a segment (for a zero-length edge).
+struct Parallelogram {
+    // In any order
+    corners: [Coord<f64>; 4]
+}
+
+// Coordinates are rounded to multiples of 1 / FIXED_POINT pixels, so that whether a pixel meets the
+// parallelogram is decided exactly in integers. Coordinates of up to 2^16 pixels keep all the products
+// below 2^60.
+const FIXED_POINT: i64 = 1 << 12;
+const HALF_PIXEL: i64 = FIXED_POINT / 2;
+
+impl Parallelogram {
+    // The corners can be given in any order
+    fn new(origin_side: (Coord<f64>, Coord<f64>), destination_side: (Coord<f64>, Coord<f64>)) -> Self {
+        Self{ corners: [origin_side.0, origin_side.1, destination_side.0, destination_side.1] }
+    }
+
+    // Column by column, from the bottom to the top of every column
+    fn pixels(&self) -> ParallelogramScanIter {
+        let corners = self.corners.map(|c| ((c.x * FIXED_POINT as f64).round() as i64, (c.y * FIXED_POINT as f64).round() as i64));
+        let x_extent = corners.iter().fold((i64::MAX, i64::MIN), |(lo, hi), c| (lo.min(c.0), hi.max(c.0)));
+
+        // The segments between two corners which do not strictly separate the other two, these are the sides
+        // of the convex hull of the corners and possibly segments within it, but not its diagonals
+        let mut sides = [((0, 0), (0, 0)); 6];
+        let mut side_count = 0;
+        for i in 0..4 {
+            for j in i + 1..4 {
+                let (a, b) = if corners[i].0 <= corners[j].0 { (corners[i], corners[j]) } else { (corners[j], corners[i]) };
+                let mut others = (0..4).filter(|k| *k != i && *k != j).map(|k| orientation(a, b, corners[k]));
+                let (first, second) = (others.next().unwrap(), others.next().unwrap());
+
+                // Vertical sides only bound the columns, which their corners already do
+                if first * second >= 0 && a.0 != b.0 {
+                    sides[side_count] = (a, b);
+                    side_count += 1;
+                }
+            }
+        }
+
+        ParallelogramScanIter{
+            corners,
+            sides,
+            side_count,
+            x_extent,
+            x: first_pixel(x_extent.0, 1) - 1,
+            x_to: last_pixel(x_extent.1, 1),
+            y: 0,
+            y_to: -1
+        }
+    }
+}
+
+// The sign of the turn from a to b to c, positive if counterclockwise, zero if they are collinear
+fn orientation(a: (i64, i64), b: (i64, i64), c: (i64, i64)) -> i64 {
+    ((b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)).signum()
+}
+
+// The first pixel whose open interval (p - 0.5, p + 0.5) ends after `lo / denominator` (in fixed point)
+fn first_pixel(lo: i64, denominator: i64) -> i32 {
+    ((lo - HALF_PIXEL * denominator).div_euclid(FIXED_POINT * denominator) + 1) as i32
+}
+
+// The last pixel whose open interval (p - 0.5, p + 0.5) starts before `hi / denominator` (in fixed point)
+fn last_pixel(hi: i64, denominator: i64) -> i32 {
+    (-(-hi - HALF_PIXEL * denominator).div_euclid(FIXED_POINT * denominator) - 1) as i32
+}
+
+struct ParallelogramScanIter {
+    // In fixed point
+    corners: [(i64, i64); 4],
+    // From left to right, the first `side_count` ones
+    sides: [((i64, i64), (i64, i64)); 6],
+    side_count: usize,
+    x_extent: (i64, i64),
+    x: i32,
+    x_to: i32,
+    y: i32,
+    y_to: i32
+}
+
+impl ParallelogramScanIter {
+    // The first and the last pixel of the column between x_from and x_to (in fixed point). The lowest and the
+    // highest y of the parallelogram there are attained at its corners or where its sides cross the two verticals.
+    fn y_range(&self, x_from: i64, x_to: i64) -> (i32, i32) {
+        let mut range = (i32::MAX, i32::MIN);
+        let mut add = |y: i64, denominator: i64| range = (range.0.min(first_pixel(y, denominator)), range.1.max(last_pixel(y, denominator)));
+
+        for corner in &self.corners {
+            if x_from <= corner.0 && corner.0 <= x_to {
+                add(corner.1, 1);
+            }
+        }
+
+        for (a, b) in &self.sides[..self.side_count] {
+            for x in [x_from, x_to] {
+                if a.0 < x && x < b.0 {
+                    // y = a.y + (x - a.x) * (b.y - a.y) / (b.x - a.x), as a fraction with a positive denominator
+                    let denominator = b.0 - a.0;
+                    add(a.1 * denominator + (x - a.0) * (b.1 - a.1), denominator);
+                }
+            }
+        }
+
+        range
+    }
+}
+
+impl Iterator for ParallelogramScanIter {
+    type Item = Coord<i16>;
+
+    fn next(&mut self) -> Option<Coord<i16>> {
+        while self.y > self.y_to {
+            if self.x >= self.x_to {
+                return None
+            }
+
+            self.x += 1;
+
+            let x = self.x as i64 * FIXED_POINT;
+            let (y, y_to) = self.y_range((x - HALF_PIXEL).max(self.x_extent.0), (x + HALF_PIXEL).min(self.x_extent.1));
+
+            self.y = y;
+            self.y_to = y_to;
+        }
+
+        let res = Coord{x: self.x as i16, y: self.y as i16};
+        self.y += 1;
+
+        Some(res)
+    }
+}
+
+// The first implementation of the pixels of a parallelogram, kept to compare with in the tests.
+// It can miss the last column and fails on some shapes, see the tests.
+#[cfg(test)]
 struct ParallelogramPixels {
     origin_side: (Coord<f64>, Coord<f64>),
     destination_side: (Coord<f64>, Coord<f64>)
 }
 
+#[cfg(test)]
 impl ParallelogramPixels {
     fn new(origin_side: (Coord<f64>, Coord<f64>), destination_side: (Coord<f64>, Coord<f64>)) -> Self {
         let mut res = Self{ origin_side, destination_side };
@@ -141,14 +320,15 @@ impl ParallelogramPixels {
     }
 }
 
+#[cfg(test)]
 impl Display for ParallelogramPixels {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "original side: {} {}, destination side: {} {}", self.origin_side.0, self.origin_side.1, self.destination_side.0, self.destination_side.1)
     }
 }
 
+#[cfg(test)]
 struct ParallelogramPixelsIter <'a> {
-    parallelogram: &'a ParallelogramPixels,
     x: i16,
     y: i16,
     current_range: (i16, i16),
@@ -158,9 +338,55 @@ struct ParallelogramPixelsIter <'a> {
     t: &'a Coord<f64>
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NeighbourhoodShape {
+    Square,
+    // Cells within a euclidean distance of `search_limit` pixels
+    Circle
+}
+
+// Which cells within `search_limit` of a cell are its neighbours. With `primitive_only` the moves
+// which are multiples of shorter moves (e.g. (2, 2) of (1, 1)) are left out.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Neighbourhood {
+    pub shape: NeighbourhoodShape,
+    pub primitive_only: bool
+}
+
+impl Neighbourhood {
+    pub const SQUARE: Self = Self{ shape: NeighbourhoodShape::Square, primitive_only: false };
+
+    // The 8-, 16- or 32-connected neighbourhood with its search limit,
+    // the primitive moves within a square of side 3, 5 or 7
+    pub fn connected(k: u16) -> Option<(Self, i16)> {
+        let search_limit = match k {
+            8 => 1,
+            16 => 2,
+            32 => 3,
+            _ => return None
+        };
+
+        Some((Self{ shape: NeighbourhoodShape::Square, primitive_only: true }, search_limit))
+    }
+
+    pub fn contains(&self, d: Coord<i16>, search_limit: i16) -> bool {
+        let within = match self.shape {
+            NeighbourhoodShape::Square => d.x.abs() <= search_limit && d.y.abs() <= search_limit,
+            NeighbourhoodShape::Circle => (d.x as i32).pow(2) + (d.y as i32).pow(2) <= (search_limit as i32).pow(2)
+        };
+
+        within && d != Coord{x: 0, y: 0} && (!self.primitive_only || gcd(d.x, d.y) == 1)
+    }
+}
+
+pub fn gcd(a: i16, b: i16) -> i16 {
+    if b == 0 { a.abs() } else { gcd(b, a % b) }
+}
+
 pub struct NeighboursIter <'a> {
     map: &'a RiskMap,
     search_limit: i16,
+    neighbourhood: Neighbourhood,
     p: Coord<i16>,
     current_x: i16,
     current_y: i16,
@@ -171,7 +397,7 @@ pub struct NeighboursIter <'a> {
 }
 
 impl <'a> NeighboursIter <'a> {
-    fn new(map: &'a RiskMap, search_limit: i16, p: Coord<i16>) -> Self {
+    fn new(map: &'a RiskMap, search_limit: i16, p: Coord<i16>, neighbourhood: Neighbourhood) -> Self {
         let width= map.width() as i16;
         let height = map.height() as i16;
 
@@ -183,6 +409,7 @@ impl <'a> NeighboursIter <'a> {
         return NeighboursIter{
             map: map,
             search_limit,
+            neighbourhood,
             p,
             current_x: x_from,
             current_y: y_from,
@@ -207,19 +434,23 @@ impl <'a> Iterator for NeighboursIter <'a> {
     type Item = Coord<i16>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current_x + self.map.offset == self.p.x && self.current_y + self.map.offset == self.p.y {
-            self.propagate()
-        }
+        loop {
+            if self.current_x + self.map.offset == self.p.x && self.current_y + self.map.offset == self.p.y {
+                self.propagate()
+            }
 
-        if self.current_y > self.y_to {
-            return None
-        }
+            if self.current_y > self.y_to {
+                return None
+            }
 
-        let res = Coord{x: self.current_x + self.map.offset, y: self.current_y + self.map.offset};
+            let res = Coord{x: self.current_x + self.map.offset, y: self.current_y + self.map.offset};
 
-        self.propagate();
+            self.propagate();
 
-        return Some(res);
+            if self.neighbourhood == Neighbourhood::SQUARE || self.neighbourhood.contains(Coord{x: res.x - self.p.x, y: res.y - self.p.y}, self.search_limit) {
+                return Some(res);
+            }
+        }
     }
 }
 
@@ -260,6 +491,7 @@ impl <'a> Iterator for AllPointsIter <'a> {
     }
 }
 
+#[cfg(test)]
 impl<'a> ParallelogramPixelsIter <'a> {
     fn new(par: &'a ParallelogramPixels) -> Self {
         let points: Vec<&Coord<f64>> = vec![&par.origin_side.0, &par.origin_side.1, &par.destination_side.0, &par.destination_side.1];
@@ -274,7 +506,7 @@ impl<'a> ParallelogramPixelsIter <'a> {
         let (b, t): (&Coord<f64>, &Coord<f64>) = mid_points.iter().cloned()
             .sorted_by_key(|k| (OrderedFloat(k.y), OrderedFloat(k.x)))
             .next_tuple().unwrap();
-        let mut res = Self{parallelogram: par, x: l.x.floor() as i16, y: 0, current_range: (0, 0), l: l, r: r, b: b, t: t};
+        let mut res = Self{x: l.x.floor() as i16, y: 0, current_range: (0, 0), l: l, r: r, b: b, t: t};
         let current_range = res.y_range(res.x);
         res.current_range = current_range;
 
@@ -348,6 +580,7 @@ impl<'a> ParallelogramPixelsIter <'a> {
     }
 }
 
+#[cfg(test)]
 impl <'a> Iterator for ParallelogramPixelsIter<'a> {
     type Item = Coord<i16>;
 
@@ -355,10 +588,6 @@ impl <'a> Iterator for ParallelogramPixelsIter<'a> {
         if self.x <= self.r.x as i16 {
             let res = Some(Coord{x: self.x, y: self.y});
 
-            if self.y > 5000 {
-                println!("y is > 5000 = {}, {}, {}, {}", self.y, self.current_range.0, self.current_range.1, self.parallelogram);
-            }
-
             if self.y + 1 > self.current_range.1 {
                 self.x += 1;
                 self.current_range = self.y_range(self.x);
@@ -374,6 +603,7 @@ impl <'a> Iterator for ParallelogramPixelsIter<'a> {
     }
 }
 
+#[cfg(test)]
 fn get_min_y(p0: &Coord<f64>, p1: &Coord<f64>, x: f64) -> i16 {
     // Thanks to Markus Jarderot from https://stackoverflow.com/questions/5610616/finding-all-pixels-at-least-partially-within-an-arbitrarily-oriented-rectangle
     // for inspiration.
@@ -400,6 +630,7 @@ fn get_min_y(p0: &Coord<f64>, p1: &Coord<f64>, x: f64) -> i16 {
     }
 }
 
+#[cfg(test)]
 fn get_max_y(p0: &Coord<f64>, p1: &Coord<f64>, x: f64) -> i16 {
     // Thanks to Markus Jarderot from https://stackoverflow.com/questions/5610616/finding-all-pixels-at-least-partially-within-an-arbitrarily-oriented-rectangle
     // for inspiration.
@@ -1,6 +1,8 @@
 #[cfg(test)]
 mod tests {
     use super::super::{*};
+    use crate::zones::zones::{Zone, PenaltyZone};
+    use proptest::prelude::*;
 
     #[test]
     fn test_simple_rectangle() {
@@ -314,34 +316,28 @@ mod tests {
                                 Coord{x: 5, y: 3},
         ]);
     }
-    #[test]
-    fn test_parallelogram_pixels_bug() {
-        // (61, 160) (60.99999999999999, 200), destination side: (57, 160) (56.99999999999999, 200)
-
-        // let res = RiskMap::parallelogram_from_two_points(Coord{x: 0, y: 0}, Coord{x: 1, y: 0}, 300.0, 15.0);
 
-        let pp = ParallelogramPixels::new(
+    #[test]
+    fn test_nearly_axis_aligned_parallelogram() {
+        // Sides rounded just below y = 61 and y = 57
+        let (origin_side, destination_side) = (
             (Coord {y: 61.0, x: 160.0}, Coord {y: 60.99999999999999, x: 200.0}),
             (Coord {y: 57.0, x: 160.0}, Coord {y: 56.99999999999999, x: 200.0})
         );
+        let expected: Vec<Coord<i16>> = (160..=200).flat_map(|x| (57..=61).map(move |y| Coord{x, y})).collect();
 
-        let mut pixels: Vec<Coord<i16>> = vec![];
-        for coord in pp.iter() {
-            pixels.push(coord);
-        }
-
-        // assert_eq!(pixels.len(), 20);
-        assert_eq!(pixels, vec![
-            Coord{x: 0, y: 1},
-        ]);
+        assert_eq!(Parallelogram::new(origin_side, destination_side).pixels().collect::<Vec<_>>(), expected);
+        assert_eq!(ParallelogramPixels::new(origin_side, destination_side).iter().collect::<Vec<_>>(), expected);
     }
 
     #[test]
     fn test_neighbours_1() {
         let mut map = RiskMap{
-            map: vec![vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0]],
+            map: Grid::from_rows(vec![vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0]]),
             m_per_pixel: 1.0,
-            offset: 0
+            offset: 0,
+            no_fly_zones: None,
+            penalty_zones: None
         };
 
         let mut coords: Vec<Coord<i16>> = vec![];
@@ -365,9 +361,11 @@ mod tests {
     #[test]
     fn test_neighbours_2() {
         let mut map = RiskMap{
-            map: vec![vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0]],
+            map: Grid::from_rows(vec![vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0]]),
             m_per_pixel: 1.0,
-            offset: 0
+            offset: 0,
+            no_fly_zones: None,
+            penalty_zones: None
         };
 
         let mut coords: Vec<Coord<i16>> = vec![];
@@ -383,9 +381,11 @@ mod tests {
     #[test]
     fn test_neighbours_3() {
         let mut map = RiskMap{
-            map: vec![vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0]],
+            map: Grid::from_rows(vec![vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0]]),
             m_per_pixel: 1.0,
-            offset: 0
+            offset: 0,
+            no_fly_zones: None,
+            penalty_zones: None
         };
 
         let mut coords: Vec<Coord<i16>> = vec![];
@@ -409,9 +409,11 @@ mod tests {
     #[test]
     fn test_neighbours_4() {
         let mut map = RiskMap{
-            map: vec![vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0]],
+            map: Grid::from_rows(vec![vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0]]),
             m_per_pixel: 1.0,
-            offset: 1
+            offset: 1,
+            no_fly_zones: None,
+            penalty_zones: None
         };
 
         let mut coords: Vec<Coord<i16>> = vec![];
@@ -430,9 +432,11 @@ mod tests {
     #[test]
     fn test_all_points_1() {
         let mut map = RiskMap{
-            map: vec![vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0]],
+            map: Grid::from_rows(vec![vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0]]),
             m_per_pixel: 1.0,
-            offset: 0
+            offset: 0,
+            no_fly_zones: None,
+            penalty_zones: None
         };
 
         let mut coords: Vec<Coord<i16>> = vec![];
@@ -464,9 +468,11 @@ mod tests {
     #[test]
     fn test_all_points_2() {
         let mut map = RiskMap{
-            map: vec![vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0]],
+            map: Grid::from_rows(vec![vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0], vec![0, 0, 0, 0]]),
             m_per_pixel: 1.0,
-            offset: 1
+            offset: 1,
+            no_fly_zones: None,
+            penalty_zones: None
         };
 
         let mut coords: Vec<Coord<i16>> = vec![];
@@ -482,4 +488,236 @@ mod tests {
             Coord{x: 2, y: 2},
         ]);
     }
+
+    #[test]
+    fn test_penalty_zones() {
+        let mut map = RiskMap{
+            map: Grid::new(10, 10, 1),
+            m_per_pixel: 1.0,
+            offset: 0,
+            no_fly_zones: None,
+            penalty_zones: None
+        };
+        let without_penalty = map.risk(Coord{x: 1, y: 1}, Coord{x: 8, y: 1}, 1.0);
+
+        let school = Zone{
+            name: "school".to_string(),
+            polygon: vec![Coord{x: 4.5, y: 1.5}, Coord{x: 5.5, y: 1.5}, Coord{x: 5.5, y: 3.5}, Coord{x: 4.5, y: 3.5}],
+            holes: vec![]
+        };
+        map.penalty_zones = Some(PenaltyMap::new(&[PenaltyZone{zone: school, factor: 4.0}], 10, 10));
+
+        assert_eq!(map.risk(Coord{x: 1, y: 1}, Coord{x: 8, y: 1}, 1.0), without_penalty * 4);
+        assert_eq!(map.risk(Coord{x: 1, y: 6}, Coord{x: 8, y: 6}, 1.0), without_penalty);
+    }
+
+    fn neighbours_count(search_limit: i16, neighbourhood: Neighbourhood) -> usize {
+        let map = RiskMap{
+            map: Grid::new(9, 9, 0),
+            m_per_pixel: 1.0,
+            offset: 0,
+            no_fly_zones: None,
+            penalty_zones: None
+        };
+
+        map.neighbours_in(Coord{x: 4, y: 4}, search_limit, neighbourhood).count()
+    }
+
+    #[test]
+    fn test_neighbourhoods() {
+        assert_eq!(neighbours_count(2, Neighbourhood::SQUARE), 24);
+        assert_eq!(neighbours_count(2, Neighbourhood{shape: NeighbourhoodShape::Square, primitive_only: true}), 16);
+        assert_eq!(neighbours_count(2, Neighbourhood{shape: NeighbourhoodShape::Circle, primitive_only: false}), 12);
+        assert_eq!(neighbours_count(2, Neighbourhood{shape: NeighbourhoodShape::Circle, primitive_only: true}), 8);
+
+        for k in [8, 16, 32] {
+            let (neighbourhood, search_limit) = Neighbourhood::connected(k).unwrap();
+            assert_eq!(neighbours_count(search_limit, neighbourhood), k as usize);
+        }
+
+        assert_eq!(Neighbourhood::connected(12), None);
+    }
+
+    #[test]
+    fn test_neighbourhood_at_the_border() {
+        let map = RiskMap{
+            map: Grid::new(9, 9, 0),
+            m_per_pixel: 1.0,
+            offset: 0,
+            no_fly_zones: None,
+            penalty_zones: None
+        };
+        let (neighbourhood, search_limit) = Neighbourhood::connected(16).unwrap();
+        let neighbours: Vec<Coord<i16>> = map.neighbours_in(Coord{x: 0, y: 0}, search_limit, neighbourhood).collect();
+
+        assert_eq!(neighbours, vec![Coord{x: 1, y: 0}, Coord{x: 0, y: 1}, Coord{x: 1, y: 1}, Coord{x: 2, y: 1}, Coord{x: 1, y: 2}]);
+    }
+
+    // How deep the pixel and the parallelogram overlap: the smallest overlap of their projections on the axes
+    // of the pixel and the normals of the sides of the parallelogram, not positive if they only touch or are apart.
+    // The corners may be in any order, so the normals of both diagonals are tried too, which cannot find a
+    // smaller overlap than the one along the sides.
+    fn overlap_depth(corners: &[Coord<f64>; 4], pixel: Coord<i16>) -> f64 {
+        let (x, y) = (pixel.x as f64, pixel.y as f64);
+        let square = [Coord{x: x - 0.5, y: y - 0.5}, Coord{x: x + 0.5, y: y - 0.5}, Coord{x: x + 0.5, y: y + 0.5}, Coord{x: x - 0.5, y: y + 0.5}];
+
+        let mut axes = vec![Coord{x: 1.0, y: 0.0}, Coord{x: 0.0, y: 1.0}];
+        for (a, b) in (0..4).flat_map(|i| (i + 1..4).map(move |j| (corners[i], corners[j]))) {
+            let length = ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt();
+            if length > 0.0 {
+                axes.push(Coord{x: -(b.y - a.y) / length, y: (b.x - a.x) / length});
+            }
+        }
+
+        let project = |points: &[Coord<f64>], axis: &Coord<f64>| points.iter()
+            .map(|p| p.x * axis.x + p.y * axis.y)
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+
+        axes.iter()
+            .map(|axis| {
+                let ((lo1, hi1), (lo2, hi2)) = (project(corners, axis), project(&square, axis));
+                hi1.min(hi2) - lo1.max(lo2)
+            })
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    // Pixels overlapping the parallelogram by less than this may be left out or included
+    const AMBIGUOUS_DEPTH: f64 = 1e-3;
+
+    // The pixels missing from the iterated ones and the pixels iterated though they do not overlap the parallelogram
+    fn compare_with_brute_force(corners: &[Coord<f64>; 4], pixels: &[Coord<i16>]) -> (Vec<Coord<i16>>, Vec<Coord<i16>>) {
+        let (x_from, x_to) = (corners.iter().map(|c| c.x).fold(f64::INFINITY, f64::min), corners.iter().map(|c| c.x).fold(f64::NEG_INFINITY, f64::max));
+        let (y_from, y_to) = (corners.iter().map(|c| c.y).fold(f64::INFINITY, f64::min), corners.iter().map(|c| c.y).fold(f64::NEG_INFINITY, f64::max));
+
+        let missing = (x_from.floor() as i16 - 1..=x_to.ceil() as i16 + 1)
+            .flat_map(|x| (y_from.floor() as i16 - 1..=y_to.ceil() as i16 + 1).map(move |y| Coord{x, y}))
+            .filter(|pixel| overlap_depth(corners, *pixel) > AMBIGUOUS_DEPTH && !pixels.contains(pixel))
+            .collect();
+        let extra = pixels.iter()
+            .copied()
+            .filter(|pixel| overlap_depth(corners, *pixel) < -AMBIGUOUS_DEPTH)
+            .collect();
+
+        (missing, extra)
+    }
+
+    fn distinct(pixels: &[Coord<i16>]) -> bool {
+        let mut sorted = pixels.to_vec();
+        sorted.sort_by_key(|c| (c.x, c.y));
+        sorted.dedup();
+
+        sorted.len() == pixels.len()
+    }
+
+    // Segments and widths like the edges of a search, away from the border of the map
+    fn corridors() -> impl Strategy<Value = (Coord<i16>, Coord<i16>, f64)> {
+        (20i16..80, 20i16..80, -8i16..=8, -8i16..=8, 1.0f64..300.0)
+            .prop_filter("the segment must not be a point", |(_, _, dx, dy, _)| *dx != 0 || *dy != 0)
+            .prop_map(|(x, y, dx, dy, r_m)| (Coord{x, y}, Coord{x: x + dx, y: y + dy}, r_m))
+    }
+
+    type Side = (Coord<f64>, Coord<f64>);
+
+    // Parallelograms of any orientation and shape, given by a corner and two sides
+    fn parallelograms() -> impl Strategy<Value = (Side, Side)> {
+        (0.0f64..50.0, 0.0f64..50.0, -20.0f64..20.0, -20.0f64..20.0, -20.0f64..20.0, -20.0f64..20.0)
+            .prop_map(|(x, y, ux, uy, vx, vy)| (
+                (Coord{x, y}, Coord{x: x + ux, y: y + uy}),
+                (Coord{x: x + vx, y: y + vy}, Coord{x: x + ux + vx, y: y + uy + vy})
+            ))
+    }
+
+    proptest! {
+        #[test]
+        fn test_parallelogram_pixels_are_distinct((p1, p2, r_m) in corridors()) {
+            prop_assert!(distinct(&corridor_pixels(p1, p2, r_m, 1000.0/(131.0/2.0))));
+        }
+
+        #[test]
+        fn test_parallelogram_pixels_brute_force((p1, p2, r_m) in corridors()) {
+            let (orig_side, dest_side) = RiskMap::parallelogram_from_two_points(p1, p2, r_m, 1000.0/(131.0/2.0));
+            let parallelogram = Parallelogram::new(orig_side, dest_side);
+            let pixels: Vec<Coord<i16>> = parallelogram.pixels().collect();
+
+            let (missing, extra) = compare_with_brute_force(&parallelogram.corners, &pixels);
+
+            prop_assert!(missing.is_empty() && extra.is_empty(), "{:?}: missing {:?}, extra {:?}", parallelogram.corners, missing, extra);
+        }
+
+        #[test]
+        fn test_any_parallelogram_brute_force((origin_side, destination_side) in parallelograms()) {
+            let parallelogram = Parallelogram::new(origin_side, destination_side);
+            let pixels: Vec<Coord<i16>> = parallelogram.pixels().collect();
+
+            let (missing, extra) = compare_with_brute_force(&parallelogram.corners, &pixels);
+
+            prop_assert!(distinct(&pixels));
+            prop_assert!(missing.is_empty() && extra.is_empty(), "{:?}: missing {:?}, extra {:?}", parallelogram.corners, missing, extra);
+        }
+    }
+
+    // The pixels the first implementation misses and those it takes in excess, against the brute-force
+    // overlap test, which it fails on many edges
+    fn old_pixels_brute_force(p1: Coord<i16>, p2: Coord<i16>, r_m: f64) -> (Vec<Coord<i16>>, Vec<Coord<i16>>) {
+        let (orig_side, dest_side) = RiskMap::parallelogram_from_two_points(p1, p2, r_m, 1000.0/(131.0/2.0));
+        let pixels: Vec<Coord<i16>> = ParallelogramPixels::new(orig_side, dest_side).iter().collect();
+
+        compare_with_brute_force(&Parallelogram::new(orig_side, dest_side).corners, &pixels)
+    }
+
+    // The case proptest shrank the first failure to: the parallelogram ends at x = 21.7, in the left half
+    // of the column of pixel 22, which the first implementation leaves out
+    #[test]
+    fn test_old_parallelogram_pixels_miss_last_column() {
+        let (missing, extra) = old_pixels_brute_force(Coord{x: 20, y: 20}, Coord{x: 21, y: 19}, 1.0);
+
+        assert_eq!(missing, vec![Coord{x: 22, y: 20}]);
+        assert!(extra.is_empty());
+    }
+
+    // On flat edges the first implementation also takes a pixel next to the origin end which the parallelogram
+    // does not overlap
+    #[test]
+    fn test_old_parallelogram_pixels_exceed_first_column() {
+        let (missing, extra) = old_pixels_brute_force(Coord{x: 20, y: 20}, Coord{x: 22, y: 19}, 1.0);
+
+        assert!(missing.is_empty());
+        assert_eq!(extra, vec![Coord{x: 19, y: 19}]);
+    }
+
+    #[test]
+    fn test_degenerate_parallelogram() {
+        // The footprint of a zero-length edge, as in `RiskMap::risk(p, p, r_m)`
+        let (orig_side, dest_side) = RiskMap::parallelogram_from_two_points(Coord{x: 10, y: 10}, Coord{x: 10, y: 10}, 3.0, 1.0);
+        let pixels: Vec<Coord<i16>> = Parallelogram::new(orig_side, dest_side).pixels().collect();
+
+        assert_eq!(pixels, (7..=13).map(|y| Coord{x: 10, y}).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_scanline_against_old_iterator() {
+        let shapes = [
+            ((Coord {x: 0.0, y: 0.0}, Coord {x: 0.0, y: 3.0}), (Coord {x: 2.0, y: 0.0}, Coord {x: 2.0, y: 3.0})),
+            ((Coord {x: 0.0, y: 0.0}, Coord {x: 1.0, y: 1.0}), (Coord {x: 2.0, y: 0.0}, Coord {x: 3.0, y: 1.0})),
+            ((Coord {x: 0.0, y: 0.0}, Coord {x: 2.0, y: 1.0}), (Coord {x: 2.0, y: 0.0}, Coord {x: 4.0, y: 1.0})),
+            ((Coord {x: 0.0, y: 1.0}, Coord {x: 1.0, y: 0.0}), (Coord {x: 1.0, y: 1.0}, Coord {x: 2.0, y: 0.0})),
+            ((Coord {x: 0.0, y: 2.0}, Coord {x: 1.0, y: 0.0}), (Coord {x: 3.0, y: 0.0}, Coord {x: 2.0, y: 2.0})),
+            ((Coord {x: 0.0, y: 0.0}, Coord {x: 0.0, y: 2.0}), (Coord {x: 1.0, y: 2.0}, Coord {x: 1.0, y: 4.0})),
+            ((Coord {x: 0.0, y: 2.0}, Coord {x: 1.0, y: 2.0}), (Coord {x: 2.0, y: 0.0}, Coord {x: 3.0, y: 0.0})),
+            ((Coord {x: 0.0, y: 3.0}, Coord {x: 1.0, y: 3.0}), (Coord {x: 2.0, y: 0.0}, Coord {x: 3.0, y: 0.0})),
+            ((Coord {x: 0.0, y: 0.0}, Coord {x: 1.0, y: 0.0}), (Coord {x: 2.0, y: 3.0}, Coord {x: 3.0, y: 3.0})),
+            ((Coord {x: 0.0, y: 0.0}, Coord {x: 1.0, y: 0.0}), (Coord {x: 2.0, y: 2.0}, Coord {x: 3.0, y: 2.0})),
+            ((Coord {x: 0.0, y: 0.0}, Coord {x: 1.0, y: 2.0}), (Coord {x: 5.0, y: 0.0}, Coord {x: 4.0, y: -2.0})),
+            ((Coord {x: 1.0, y: 0.0}, Coord {x: 0.0, y: 2.0}), (Coord {x: 5.0, y: 2.0}, Coord {x: 4.0, y: 4.0}))
+        ];
+
+        for (origin_side, destination_side) in shapes {
+            let mut old: Vec<Coord<i16>> = ParallelogramPixels::new(origin_side, destination_side).iter().collect();
+            let mut new: Vec<Coord<i16>> = Parallelogram::new(origin_side, destination_side).pixels().collect();
+            old.sort_by_key(|c| (c.x, c.y));
+            new.sort_by_key(|c| (c.x, c.y));
+
+            assert_eq!(new, old, "{:?} {:?}", origin_side, destination_side);
+        }
+    }
 }
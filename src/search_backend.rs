@@ -0,0 +1 @@
+pub mod search_backend;
\ No newline at end of file
@@ -0,0 +1,196 @@
+#[cfg(test)]
+#[path = "tests.rs"]
+mod tests;
+
+use std::cmp::Reverse;
+use hashbrown::HashMap;
+use hashbrown::hash_map::DefaultHashBuilder;
+use priority_queue::PriorityQueue;
+use ordered_float::OrderedFloat;
+use crate::risks::risks::Coord;
+use crate::grid::grid::Grid;
+
+// How the labels of the cells are kept during a search
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LabelStorage {
+    // Hash maps of the reached cells, small when a search reaches few cells of a large map
+    Hash,
+    // Flat arrays over all the cells of the map
+    Dense
+}
+
+// The priority queue of a search
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum QueueKind {
+    // A binary heap with decrease-key
+    Binary,
+    // A radix heap, which pops in order of label only for searches pushing labels no lower than the popped ones
+    Radix
+}
+
+// The labels of the cells with the cells they were reached from
+pub trait Labels {
+    // Infinite for cells which were not reached
+    fn label(&self, node: Coord<i16>) -> f64;
+    fn previous(&self, node: Coord<i16>) -> Option<Coord<i16>>;
+    fn set(&mut self, node: Coord<i16>, label: f64, previous: Coord<i16>);
+    // All the labels on a grid with `rows` x `cols` cells
+    fn to_grid(&self, rows: usize, cols: usize) -> Grid<f64>;
+}
+
+pub struct HashLabels {
+    labels: HashMap<Coord<i16>, (f64, Coord<i16>)>
+}
+
+impl HashLabels {
+    pub fn new() -> Self {
+        Self{ labels: HashMap::new() }
+    }
+}
+
+impl Default for HashLabels {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Labels for HashLabels {
+    fn label(&self, node: Coord<i16>) -> f64 {
+        self.labels.get(&node).map_or(f64::INFINITY, |(label, _)| *label)
+    }
+
+    fn previous(&self, node: Coord<i16>) -> Option<Coord<i16>> {
+        self.labels.get(&node).map(|(_, previous)| *previous)
+    }
+
+    fn set(&mut self, node: Coord<i16>, label: f64, previous: Coord<i16>) {
+        self.labels.insert(node, (label, previous));
+    }
+
+    fn to_grid(&self, rows: usize, cols: usize) -> Grid<f64> {
+        let mut res = Grid::new(rows, cols, f64::INFINITY);
+
+        for (node, (label, _)) in self.labels.iter() {
+            res.set(node.y as usize, node.x as usize, *label);
+        }
+
+        res
+    }
+}
+
+// Labels stored row by row, at y * cols + x
+pub struct DenseLabels {
+    labels: Grid<f64>,
+    previous_nodes: Grid<Option<Coord<i16>>>
+}
+
+impl DenseLabels {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        Self{ labels: Grid::new(rows, cols, f64::INFINITY), previous_nodes: Grid::new(rows, cols, None) }
+    }
+}
+
+impl Labels for DenseLabels {
+    fn label(&self, node: Coord<i16>) -> f64 {
+        self.labels.at(node)
+    }
+
+    fn previous(&self, node: Coord<i16>) -> Option<Coord<i16>> {
+        self.previous_nodes.at(node)
+    }
+
+    fn set(&mut self, node: Coord<i16>, label: f64, previous: Coord<i16>) {
+        self.labels.set(node.y as usize, node.x as usize, label);
+        self.previous_nodes.set(node.y as usize, node.x as usize, Some(previous));
+    }
+
+    fn to_grid(&self, _rows: usize, _cols: usize) -> Grid<f64> {
+        self.labels.clone()
+    }
+}
+
+// Cells ordered by their labels, the lowest first. A queue may keep outdated entries of a cell
+// after its label was lowered, they are popped after the current one.
+pub trait SearchQueue {
+    fn push(&mut self, node: Coord<i16>, label: f64);
+    fn pop(&mut self) -> Option<(Coord<i16>, f64)>;
+}
+
+pub struct BinaryQueue {
+    pq: PriorityQueue<Coord<i16>, Reverse<OrderedFloat<f64>>, DefaultHashBuilder>
+}
+
+impl BinaryQueue {
+    pub fn new() -> Self {
+        Self{ pq: PriorityQueue::with_default_hasher() }
+    }
+}
+
+impl Default for BinaryQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SearchQueue for BinaryQueue {
+    fn push(&mut self, node: Coord<i16>, label: f64) {
+        self.pq.push(node, Reverse(OrderedFloat(label)));
+    }
+
+    fn pop(&mut self) -> Option<(Coord<i16>, f64)> {
+        self.pq.pop().map(|(node, label)| (node, (label.0).0))
+    }
+}
+
+// A monotone radix heap over the bits of the labels, which for non-negative floats are ordered
+// like the labels. Entries are kept in buckets by the highest bit in which they differ from the
+// last popped label; a pop only sorts out the lowest non-empty bucket. A label below the last popped
+// one, which a search with decreasing labels may push, is keyed as the last popped label, so it is
+// popped next though not necessarily in order.
+pub struct RadixHeap {
+    last: u64,
+    // The keys with the labels and the cells
+    buckets: Vec<Vec<(u64, f64, Coord<i16>)>>
+}
+
+impl RadixHeap {
+    pub fn new() -> Self {
+        Self{ last: 0, buckets: vec![vec![]; 65] }
+    }
+
+    fn bucket_of(&self, key: u64) -> usize {
+        64 - (key ^ self.last).leading_zeros() as usize
+    }
+}
+
+impl Default for RadixHeap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SearchQueue for RadixHeap {
+    fn push(&mut self, node: Coord<i16>, label: f64) {
+        // The bits of negative labels are ordered the other way round
+        let key = if label > 0.0 { label.to_bits().max(self.last) } else { self.last };
+
+        let bucket = self.bucket_of(key);
+        self.buckets[bucket].push((key, label, node));
+    }
+
+    fn pop(&mut self) -> Option<(Coord<i16>, f64)> {
+        if self.buckets[0].is_empty() {
+            let bucket = self.buckets.iter().position(|b| !b.is_empty())?;
+            let entries = std::mem::take(&mut self.buckets[bucket]);
+
+            self.last = entries.iter().map(|(key, _, _)| *key).min().unwrap();
+
+            for (key, label, node) in entries {
+                let bucket = self.bucket_of(key);
+                self.buckets[bucket].push((key, label, node));
+            }
+        }
+
+        self.buckets[0].pop().map(|(_, label, node)| (node, label))
+    }
+}
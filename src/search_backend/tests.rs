@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+    use super::super::{*};
+
+    fn pop_all<Q: SearchQueue>(mut queue: Q, labels: &[f64]) -> Vec<f64> {
+        for (i, label) in labels.iter().enumerate() {
+            queue.push(Coord{x: i as i16, y: 0}, *label);
+        }
+
+        std::iter::from_fn(|| queue.pop().map(|(_, label)| label)).collect()
+    }
+
+    #[test]
+    fn test_queues() {
+        let labels: [f64; 7] = [5.5, 0.0, 1e9, 3.25, 3.25, 0.001, 42.0];
+        let mut sorted = labels.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        assert_eq!(pop_all(RadixHeap::new(), &labels), sorted);
+        // The binary queue keeps one entry per cell, all the cells are different here
+        assert_eq!(pop_all(BinaryQueue::new(), &labels), sorted);
+    }
+
+    #[test]
+    fn test_radix_heap_interleaved() {
+        let mut heap = RadixHeap::new();
+        heap.push(Coord{x: 0, y: 0}, 10.0);
+        heap.push(Coord{x: 1, y: 0}, 2.0);
+
+        assert_eq!(heap.pop(), Some((Coord{x: 1, y: 0}, 2.0)));
+
+        heap.push(Coord{x: 2, y: 0}, 2.0);
+        heap.push(Coord{x: 3, y: 0}, 7.5);
+
+        assert_eq!(heap.pop(), Some((Coord{x: 2, y: 0}, 2.0)));
+        assert_eq!(heap.pop(), Some((Coord{x: 3, y: 0}, 7.5)));
+        assert_eq!(heap.pop(), Some((Coord{x: 0, y: 0}, 10.0)));
+        assert_eq!(heap.pop(), None);
+    }
+
+    #[test]
+    fn test_radix_heap_decreasing() {
+        let mut heap = RadixHeap::new();
+        heap.push(Coord{x: 0, y: 0}, 10.0);
+        heap.push(Coord{x: 1, y: 0}, 12.0);
+        heap.pop();
+
+        // Labels below the last popped one come out next, with their own values
+        heap.push(Coord{x: 2, y: 0}, 9.0);
+        heap.push(Coord{x: 3, y: 0}, -1.0);
+        let mut popped = vec![heap.pop().unwrap(), heap.pop().unwrap()];
+        popped.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        assert_eq!(popped, vec![(Coord{x: 3, y: 0}, -1.0), (Coord{x: 2, y: 0}, 9.0)]);
+        assert_eq!(heap.pop(), Some((Coord{x: 1, y: 0}, 12.0)));
+        assert_eq!(heap.pop(), None);
+    }
+
+    #[test]
+    fn test_labels() {
+        let mut hash = HashLabels::new();
+        let mut dense = DenseLabels::new(3, 4);
+
+        for labels in [&mut hash as &mut dyn Labels, &mut dense as &mut dyn Labels] {
+            labels.set(Coord{x: 3, y: 1}, 2.5, Coord{x: 2, y: 1});
+
+            assert_eq!(labels.label(Coord{x: 3, y: 1}), 2.5);
+            assert_eq!(labels.previous(Coord{x: 3, y: 1}), Some(Coord{x: 2, y: 1}));
+            assert_eq!(labels.label(Coord{x: 0, y: 0}), f64::INFINITY);
+            assert_eq!(labels.previous(Coord{x: 0, y: 0}), None);
+        }
+
+        assert_eq!(hash.to_grid(3, 4), dense.to_grid(3, 4));
+    }
+}
@@ -0,0 +1 @@
+pub mod test_maps;
\ No newline at end of file
@@ -0,0 +1,21 @@
+// Small maps shared by the tests of the searches, with pixels of 1 m
+
+use std::ops::Range;
+use crate::grid::grid::Grid;
+use crate::risks::risks::RiskMap;
+
+pub fn risk_map(map: Grid<i32>) -> RiskMap {
+    RiskMap{ map, m_per_pixel: 1.0, offset: 0, no_fly_zones: None, penalty_zones: None }
+}
+
+// Sets the density of the block of pixels in `rows` and `cols`. Unless it spans the map, routes across
+// such a wall have to go around it.
+pub fn with_wall(mut risk_map: RiskMap, rows: Range<usize>, cols: Range<usize>, density: i32) -> RiskMap {
+    for y in rows {
+        for x in cols.clone() {
+            risk_map.map.set(y, x, density);
+        }
+    }
+
+    risk_map
+}
@@ -0,0 +1 @@
+pub mod waypoint_graph;
\ No newline at end of file
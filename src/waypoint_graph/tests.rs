@@ -0,0 +1,58 @@
+#[cfg(test)]
+mod tests {
+    use super::super::{*};
+    use crate::grid::grid::Grid;
+    use crate::bicriteria_dijkstra::bicriteria_dijkstra::BicriteriaDijkstraInstance;
+    use crate::test_maps::test_maps::{risk_map, with_wall};
+
+    #[test]
+    fn test_lattice_graph() {
+        let risk_map = risk_map(Grid::new(10, 10, 1));
+        let waypoints = lattice_waypoints(&risk_map, 3);
+
+        assert_eq!(waypoints.len(), 16);
+        assert_eq!(waypoints[..4], [Coord{x: 0, y: 0}, Coord{x: 3, y: 0}, Coord{x: 6, y: 0}, Coord{x: 9, y: 0}]);
+
+        let graph = WaypointGraph::new(&risk_map, waypoints, 3.0, 0.5);
+
+        // Only the horizontal and vertical neighbours are within 3 pixels
+        assert_eq!(graph.edges_count(), 24);
+        assert_eq!(graph.edges_from(Coord{x: 0, y: 0}).len(), 2);
+        assert_eq!(graph.edges_from(Coord{x: 3, y: 3}).len(), 4);
+        assert!(graph.edges_from(Coord{x: 1, y: 1}).is_empty());
+
+        let edge = graph.edges_from(Coord{x: 0, y: 0})[0];
+        assert_eq!(edge.risk, risk_map.risk(Coord{x: 0, y: 0}, edge.to, 0.5));
+    }
+
+    #[test]
+    #[should_panic(expected = "spacing of the waypoints must be at least 1 pixel")]
+    fn test_lattice_without_spacing() {
+        lattice_waypoints(&risk_map(Grid::new(10, 10, 1)), 0);
+    }
+
+    #[test]
+    fn test_boundary_waypoints() {
+        let mut map = Grid::new(12, 12, 1);
+        map.set(5, 5, 100);
+        let waypoints = boundary_waypoints(&risk_map(map), 2);
+
+        assert!(waypoints.contains(&Coord{x: 0, y: 0}));
+        assert!(waypoints.contains(&Coord{x: 5, y: 4}));
+        assert!(waypoints.iter().all(|p| p.x < 12 && p.y < 12));
+    }
+
+    #[test]
+    fn test_search_on_graph() {
+        let risk_map = with_wall(risk_map(Grid::new(13, 13, 1)), 0..10, 6..7, 100000);
+        let graph = WaypointGraph::new(&risk_map, lattice_waypoints(&risk_map, 2), 3.0, 0.5);
+
+        let inst = BicriteriaDijkstraInstance::new(&risk_map, Coord{x: 2, y: 2}, Coord{x: 10, y: 2}, 5, 0.5)
+            .with_waypoint_graph(&graph);
+        let path = inst.run_with_alpha(1.0);
+
+        assert_eq!((path.path[0], *path.path.last().unwrap()), (Coord{x: 10, y: 2}, Coord{x: 2, y: 2}));
+        assert!(path.path.iter().all(|p| p.x % 2 == 0 && p.y % 2 == 0));
+        assert!(path.risk < 100000);
+    }
+}
@@ -0,0 +1,115 @@
+#[cfg(test)]
+#[path = "tests.rs"]
+mod tests;
+
+use hashbrown::HashMap;
+use crate::risks::risks::{RiskMap, Coord};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct WaypointEdge {
+    pub to: Coord<i16>,
+    pub risk: i32,
+    pub length_m: f64
+}
+
+// A sparse graph of waypoints connected by long straight edges, an alternative to the grid of
+// `NeighboursIter` for large maps. The edge weights are evaluated by `RiskMap::risk` when the graph
+// is built, so that all the searches of the Pareto front can share them.
+pub struct WaypointGraph {
+    pub waypoints: Vec<Coord<i16>>,
+    index: HashMap<Coord<i16>, usize>,
+    edges: Vec<Vec<WaypointEdge>>
+}
+
+impl WaypointGraph {
+    // Connects every two waypoints at most `max_edge_px` pixels apart, except where a no-fly zone is in the way
+    pub fn new(risk_map: &RiskMap, mut waypoints: Vec<Coord<i16>>, max_edge_px: f64, r_m: f64) -> Self {
+        waypoints.sort_by_key(|p| (p.y, p.x));
+        waypoints.dedup();
+
+        let index: HashMap<Coord<i16>, usize> = waypoints.iter().enumerate().map(|(i, p)| (*p, i)).collect();
+
+        // Waypoints bucketed by cells of `max_edge_px`, only the neighbouring buckets have to be compared
+        let bucket_px = max_edge_px.ceil().max(1.0) as i16;
+        let mut buckets: HashMap<(i16, i16), Vec<usize>> = HashMap::new();
+        for (i, p) in waypoints.iter().enumerate() {
+            buckets.entry((p.x / bucket_px, p.y / bucket_px)).or_default().push(i);
+        }
+
+        let mut edges: Vec<Vec<WaypointEdge>> = vec![vec![]; waypoints.len()];
+
+        for (i, p) in waypoints.iter().enumerate() {
+            let (bx, by) = (p.x / bucket_px, p.y / bucket_px);
+
+            for nx in bx - 1..=bx + 1 {
+                for ny in by - 1..=by + 1 {
+                    for &j in buckets.get(&(nx, ny)).map(|b| b.as_slice()).unwrap_or(&[]) {
+                        let q = waypoints[j];
+                        let distance_px = (((q.x - p.x) as f64).powi(2) + ((q.y - p.y) as f64).powi(2)).sqrt();
+
+                        // Every pair once, the edge goes both ways
+                        if j <= i || distance_px > max_edge_px || risk_map.blocking_zone(*p, q, r_m).is_some() {
+                            continue;
+                        }
+
+                        let risk = risk_map.risk(*p, q, r_m);
+                        let length_m = risk_map.length_m(*p, q);
+
+                        edges[i].push(WaypointEdge{ to: q, risk, length_m });
+                        edges[j].push(WaypointEdge{ to: *p, risk, length_m });
+                    }
+                }
+            }
+        }
+
+        Self{ waypoints, index, edges }
+    }
+
+    pub fn edges_from(&self, waypoint: Coord<i16>) -> &[WaypointEdge] {
+        match self.index.get(&waypoint) {
+            Some(i) => &self.edges[*i],
+            None => &[]
+        }
+    }
+
+    pub fn edges_count(&self) -> usize {
+        self.edges.iter().map(|e| e.len()).sum::<usize>() / 2
+    }
+}
+
+// The cells of the searched area of the map (within its offset) every `spacing` pixels, which has to be positive
+pub fn lattice_waypoints(risk_map: &RiskMap, spacing: i16) -> Vec<Coord<i16>> {
+    assert!(spacing > 0, "The spacing of the waypoints must be at least 1 pixel, not {}", spacing);
+
+    let mut waypoints = vec![];
+
+    for y in (0..risk_map.height()).step_by(spacing as usize) {
+        for x in (0..risk_map.width()).step_by(spacing as usize) {
+            waypoints.push(Coord{x: x + risk_map.offset, y: y + risk_map.offset});
+        }
+    }
+
+    waypoints
+}
+
+// Cells where the density changes (to the right or below), at most one in every `spacing` x `spacing` block,
+// together with a lattice of `4 * spacing` so that uniform regions can be crossed as well
+pub fn boundary_waypoints(risk_map: &RiskMap, spacing: i16) -> Vec<Coord<i16>> {
+    let mut blocks: HashMap<(i16, i16), Coord<i16>> = HashMap::new();
+
+    for y in risk_map.offset..risk_map.offset + risk_map.height() - 1 {
+        for x in risk_map.offset..risk_map.offset + risk_map.width() - 1 {
+            let p = Coord{x, y};
+            let density = risk_map.risk_at(p);
+
+            if density != risk_map.risk_at(Coord{x: x + 1, y}) || density != risk_map.risk_at(Coord{x, y: y + 1}) {
+                blocks.entry((x / spacing, y / spacing)).or_insert(p);
+            }
+        }
+    }
+
+    let mut waypoints: Vec<Coord<i16>> = blocks.values().copied().collect();
+    waypoints.extend(lattice_waypoints(risk_map, spacing * 4));
+
+    waypoints
+}
@@ -0,0 +1 @@
+pub mod zones;
\ No newline at end of file
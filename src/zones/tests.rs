@@ -0,0 +1,152 @@
+#[cfg(test)]
+mod tests {
+    use super::super::{*};
+
+    fn square(name: &str, from: f64, to: f64) -> Zone {
+        Zone{
+            name: name.to_string(),
+            polygon: vec![Coord{x: from, y: from}, Coord{x: to, y: from}, Coord{x: to, y: to}, Coord{x: from, y: to}],
+            holes: vec![]
+        }
+    }
+
+    #[test]
+    fn test_contains() {
+        let mut zone = square("airport", 0.0, 10.0);
+
+        assert!(zone.contains(Coord{x: 5.0, y: 5.0}));
+        assert!(!zone.contains(Coord{x: 11.0, y: 5.0}));
+        assert!(!zone.contains(Coord{x: -1.0, y: 5.0}));
+
+        zone.holes.push(square("", 4.0, 6.0).polygon);
+
+        assert!(!zone.contains(Coord{x: 5.0, y: 5.0}));
+        assert!(zone.contains(Coord{x: 2.0, y: 5.0}));
+    }
+
+    #[test]
+    fn test_zone_map() {
+        let zones = ZoneMap::new(vec![square("a", 0.5, 2.5), square("b", 1.5, 3.5), square("c", -5.0, -1.0)], 5, 5);
+
+        assert_eq!(zones.zone_at(Coord{x: 0, y: 0}), None);
+        assert_eq!(zones.zone_at(Coord{x: 1, y: 1}), Some(0));
+        assert_eq!(zones.zone_at(Coord{x: 2, y: 2}), Some(0));
+        assert_eq!(zones.zone_at(Coord{x: 3, y: 3}), Some(1));
+        assert_eq!(zones.zone_at(Coord{x: 4, y: 4}), None);
+        assert_eq!(zones.zone_at(Coord{x: -1, y: -1}), None);
+    }
+
+    #[test]
+    fn test_zone_map_marks_overlapped_pixels() {
+        // Between the centres of the pixels, and a sliver along the edge of pixel (4, 1)
+        let small = Zone{name: "mast".to_string(), polygon: vec![Coord{x: 1.2, y: 1.2}, Coord{x: 1.4, y: 1.2}, Coord{x: 1.3, y: 1.4}], holes: vec![]};
+        let thin = Zone{name: "wire".to_string(), polygon: vec![Coord{x: 3.6, y: 0.0}, Coord{x: 3.7, y: 0.0}, Coord{x: 3.7, y: 5.0}], holes: vec![]};
+        let zones = ZoneMap::new(vec![small, thin], 5, 5);
+
+        assert_eq!(zones.zone_at(Coord{x: 1, y: 1}), Some(0));
+        assert_eq!(zones.zone_at(Coord{x: 2, y: 1}), None);
+        assert_eq!(zones.zone_at(Coord{x: 4, y: 1}), Some(1));
+        assert_eq!(zones.zone_at(Coord{x: 4, y: 4}), Some(1));
+        assert_eq!(zones.zone_at(Coord{x: 3, y: 1}), None);
+    }
+
+    #[test]
+    fn test_downsample_zones() {
+        let zones = ZoneMap::new(vec![square("a", 2.5, 3.5), square("b", 0.5, 3.5)], 5, 5).downsample(2);
+
+        assert_eq!(zones.zone_at(Coord{x: 0, y: 0}), Some(1));
+        assert_eq!(zones.zone_at(Coord{x: 1, y: 1}), Some(0));
+        assert_eq!(zones.zone_at(Coord{x: 2, y: 2}), None);
+        assert_eq!(zones.zone_at(Coord{x: 3, y: 3}), None);
+
+        let penalties = PenaltyMap::new(&[PenaltyZone{zone: square("school", 0.5, 1.5), factor: 3.0}, PenaltyZone{zone: square("park", 1.5, 2.5), factor: 0.5}], 5, 5)
+            .downsample(2);
+
+        assert_eq!(penalties.factor_at(Coord{x: 0, y: 0}), Some(3.0));
+        assert_eq!(penalties.factor_at(Coord{x: 1, y: 1}), Some(0.5));
+        assert_eq!(penalties.factor_at(Coord{x: 2, y: 2}), None);
+    }
+
+    #[test]
+    fn test_read_pixel_zones() {
+        let json = r#"[{"name": "prison", "polygon": [{"x": 0, "y": 0}, {"x": 4, "y": 0}, {"x": 0, "y": 4}]}]"#;
+        let zones = read_zones(json.as_bytes(), None).unwrap();
+
+        assert_eq!(zones.len(), 1);
+        assert_eq!(zones[0].name, "prison");
+        assert_eq!(zones[0].polygon[1], Coord{x: 4.0, y: 0.0});
+    }
+
+    #[test]
+    fn test_read_geojson() {
+        let georef = Georeference{lat0: 50.0, lon0: 20.0, m_per_pixel: 10.0};
+        let json = r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "properties": {"name": "airport"},
+             "geometry": {"type": "Polygon", "coordinates": [[[20.0, 50.0], [20.01, 50.0], [20.01, 49.99], [20.0, 50.0]]]}},
+            {"type": "Feature", "properties": {},
+             "geometry": {"type": "MultiPolygon", "coordinates": [[[[20.0, 50.0], [20.0, 49.9], [20.1, 49.9]]], [[[20.0, 50.0], [20.1, 50.0], [20.1, 50.1]]]]}}
+        ]}"#;
+
+        let zones = read_zones(json.as_bytes(), Some(&georef)).unwrap();
+
+        assert_eq!(zones.iter().map(|z| z.name.as_str()).collect::<Vec<_>>(), vec!["airport", "zone 1", "zone 1"]);
+        assert_eq!(zones[0].polygon[0], Coord{x: 0.0, y: 0.0});
+        assert!(zones[0].polygon[1].x > 0.0 && zones[0].polygon[2].y > 0.0);
+    }
+
+    #[test]
+    fn test_geojson_needs_georeference() {
+        let json = r#"{"type": "Feature", "properties": {}, "geometry": {"type": "Polygon", "coordinates": [[[0, 0], [1, 0], [1, 1]]]}}"#;
+
+        assert_eq!(read_zones(json.as_bytes(), None).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_penalty_map() {
+        let zones = vec![
+            PenaltyZone{zone: square("school", 0.5, 2.5), factor: 3.0},
+            PenaltyZone{zone: square("event", 1.5, 3.5), factor: 10.0},
+            PenaltyZone{zone: square("park", 3.5, 4.5), factor: 0.5}
+        ];
+        let penalties = PenaltyMap::new(&zones, 5, 5);
+
+        assert_eq!(penalties.factor_at(Coord{x: 0, y: 0}), None);
+        assert_eq!(penalties.factor_at(Coord{x: 1, y: 1}), Some(3.0));
+        assert_eq!(penalties.factor_at(Coord{x: 2, y: 2}), Some(10.0));
+        assert_eq!(penalties.factor_at(Coord{x: 4, y: 4}), Some(0.5));
+        assert_eq!(penalties.factor_at(Coord{x: 5, y: 5}), None);
+    }
+
+    #[test]
+    fn test_penalty_map_marks_overlapped_pixels() {
+        // Smaller than a pixel, inside pixel (2, 2) but away from its centre
+        let crowd = Zone{name: "crowd".to_string(), polygon: vec![Coord{x: 2.1, y: 1.6}, Coord{x: 2.3, y: 1.6}, Coord{x: 2.3, y: 1.8}, Coord{x: 2.1, y: 1.8}], holes: vec![]};
+        let penalties = PenaltyMap::new(&[PenaltyZone{zone: crowd, factor: 4.0}], 5, 5);
+
+        assert_eq!(penalties.factor_at(Coord{x: 2, y: 2}), Some(4.0));
+        assert_eq!(penalties.factor_at(Coord{x: 2, y: 1}), None);
+        assert_eq!(penalties.factor_at(Coord{x: 3, y: 2}), None);
+        assert_eq!(penalties.factor_at(Coord{x: 1, y: 2}), None);
+    }
+
+    #[test]
+    fn test_read_penalty_zones() {
+        let json = r#"[{"name": "school", "polygon": [{"x": 0, "y": 0}, {"x": 4, "y": 0}, {"x": 0, "y": 4}], "factor": 5.0}]"#;
+        let zones = read_penalty_zones(json.as_bytes(), None).unwrap();
+
+        assert_eq!(zones[0].zone.name, "school");
+        assert_eq!(zones[0].factor, 5.0);
+
+        let georef = Georeference{lat0: 50.0, lon0: 20.0, m_per_pixel: 10.0};
+        let feature = r#"{"type": "Feature", "properties": {"name": "hospital", "factor": 2.5},
+                          "geometry": {"type": "Polygon", "coordinates": [[[20.0, 50.0], [20.01, 50.0], [20.01, 49.99]]]}}"#;
+
+        assert_eq!(read_penalty_zones(feature.as_bytes(), Some(&georef)).unwrap()[0].factor, 2.5);
+
+        let no_factor = r#"[{"name": "school", "polygon": [{"x": 0, "y": 0}, {"x": 4, "y": 0}, {"x": 0, "y": 4}]}]"#;
+        let negative = r#"[{"name": "school", "polygon": [{"x": 0, "y": 0}, {"x": 4, "y": 0}, {"x": 0, "y": 4}], "factor": -1}]"#;
+
+        assert!(read_penalty_zones(no_factor.as_bytes(), None).is_err());
+        assert!(read_penalty_zones(negative.as_bytes(), None).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+#[cfg(test)]
+#[path = "tests.rs"]
+mod tests;
+
+use std::io::{self, Read, Error, ErrorKind};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::risks::risks::Coord;
+use crate::grid::grid::Grid;
+use crate::air_traffic::air_traffic::Georeference;
+
+// An area given by a polygon in pixel coordinates, e.g. an airport or a temporary restriction.
+// Holes are cut out of the polygon.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Zone {
+    pub name: String,
+    pub polygon: Vec<Coord<f64>>,
+    #[serde(default)]
+    pub holes: Vec<Vec<Coord<f64>>>
+}
+
+impl Zone {
+    // Even-odd rule over the outline and the holes
+    pub fn contains(&self, p: Coord<f64>) -> bool {
+        std::iter::once(&self.polygon)
+            .chain(self.holes.iter())
+            .filter(|ring| ring_crossings(ring, p) % 2 == 1)
+            .count() % 2 == 1
+    }
+
+    fn bounding_box(&self) -> (Coord<f64>, Coord<f64>) {
+        self.polygon.iter().fold(
+            (Coord{x: f64::INFINITY, y: f64::INFINITY}, Coord{x: f64::NEG_INFINITY, y: f64::NEG_INFINITY}),
+            |(lo, hi), p| (Coord{x: lo.x.min(p.x), y: lo.y.min(p.y)}, Coord{x: hi.x.max(p.x), y: hi.y.max(p.y)})
+        )
+    }
+}
+
+// How many edges of the ring a ray from p to the right crosses
+fn ring_crossings(ring: &[Coord<f64>], p: Coord<f64>) -> usize {
+    let mut crossings = 0;
+
+    for i in 0..ring.len() {
+        let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+
+        if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            crossings += 1;
+        }
+    }
+
+    crossings
+}
+
+// A zone where the ground risk is multiplied by `factor`, e.g. a school or an event
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PenaltyZone {
+    #[serde(flatten)]
+    pub zone: Zone,
+    pub factor: f64
+}
+
+// Calls `visit(row, col)` for every pixel of a map with `rows` x `cols` pixels whose square overlaps the zone,
+// even by a sliver: its centre is inside the zone or an edge of the zone passes through its interior
+fn for_each_overlapping_pixel<F: FnMut(usize, usize)>(zone: &Zone, rows: usize, cols: usize, mut visit: F) {
+    let (lo, hi) = zone.bounding_box();
+    let (x_from, y_from) = ((lo.x - 0.5).floor().max(0.0), (lo.y - 0.5).floor().max(0.0));
+    let (x_to, y_to) = ((hi.x + 0.5).ceil().min(cols as f64 - 1.0), (hi.y + 0.5).ceil().min(rows as f64 - 1.0));
+
+    if x_to < x_from || y_to < y_from {
+        return
+    }
+
+    for y in y_from as usize..=y_to as usize {
+        for x in x_from as usize..=x_to as usize {
+            let centre = Coord{x: x as f64, y: y as f64};
+            let crossed = std::iter::once(&zone.polygon)
+                .chain(zone.holes.iter())
+                .any(|ring| (0..ring.len()).any(|i| crosses_pixel(ring[i], ring[(i + 1) % ring.len()], centre)));
+
+            if crossed || zone.contains(centre) {
+                visit(y, x);
+            }
+        }
+    }
+}
+
+// Whether the segment from a to b passes through the interior of the pixel centred on `centre`. The segment
+// is clipped to the closed square, the clipped part is in the interior unless it only runs along the border.
+fn crosses_pixel(a: Coord<f64>, b: Coord<f64>, centre: Coord<f64>) -> bool {
+    let (mut t_from, mut t_to) = (0.0f64, 1.0f64);
+
+    for (start, delta, lo) in [(a.x, b.x - a.x, centre.x - 0.5), (a.y, b.y - a.y, centre.y - 0.5)] {
+        if delta == 0.0 {
+            if start < lo || start > lo + 1.0 {
+                return false
+            }
+        } else {
+            let (t1, t2) = ((lo - start) / delta, (lo + 1.0 - start) / delta);
+            t_from = t_from.max(t1.min(t2));
+            t_to = t_to.min(t1.max(t2));
+        }
+    }
+
+    if t_from > t_to {
+        return false
+    }
+
+    let t = (t_from + t_to) / 2.0;
+    let (x, y) = (a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t);
+
+    (x - centre.x).abs() < 0.5 && (y - centre.y).abs() < 0.5
+}
+
+// No-fly zones rasterised onto the grid of a map: a pixel belongs to a zone if its square overlaps it, so
+// that zones smaller than a pixel or clipped by a footprint are not missed. Where zones overlap, the pixel
+// belongs to the first one.
+pub struct ZoneMap {
+    pub zones: Vec<Zone>,
+    cells: Grid<i32>
+}
+
+impl ZoneMap {
+    pub fn new(zones: Vec<Zone>, rows: usize, cols: usize) -> Self {
+        let mut cells = Grid::new(rows, cols, 0);
+
+        for (index, zone) in zones.iter().enumerate().rev() {
+            for_each_overlapping_pixel(zone, rows, cols, |row, col| cells.set(row, col, index as i32 + 1));
+        }
+
+        Self{ zones, cells }
+    }
+
+    // Index of the zone containing the pixel, pixels outside of the map are in no zone
+    pub fn zone_at(&self, coord: Coord<i16>) -> Option<usize> {
+        if coord.x < 0 || coord.y < 0 || coord.x as usize >= self.cells.cols() || coord.y as usize >= self.cells.rows() {
+            return None
+        }
+
+        match self.cells.at(coord) {
+            0 => None,
+            zone => Some(zone as usize - 1)
+        }
+    }
+
+    // The zones on the map downsampled by `factor`: a coarse pixel belongs to the first zone of any of its
+    // pixels. The polygons of the zones stay in the pixels of the original map.
+    pub fn downsample(&self, factor: usize) -> Self {
+        let mut cells = Grid::new(self.cells.rows().div_ceil(factor), self.cells.cols().div_ceil(factor), 0);
+
+        for row in 0..self.cells.rows() {
+            for col in 0..self.cells.cols() {
+                let (zone, coarse) = (self.cells.get(row, col), cells.get(row / factor, col / factor));
+
+                if zone != 0 && (coarse == 0 || zone < coarse) {
+                    cells.set(row / factor, col / factor, zone);
+                }
+            }
+        }
+
+        Self{ zones: self.zones.clone(), cells }
+    }
+}
+
+// Penalty factors of the pixels of a map, the largest one where penalty zones overlap.
+// A pixel is penalised when a zone overlaps its square, even without covering its centre
+pub struct PenaltyMap {
+    factors: Grid<f64>
+}
+
+impl PenaltyMap {
+    pub fn new(zones: &[PenaltyZone], rows: usize, cols: usize) -> Self {
+        let mut factors: Grid<f64> = Grid::new(rows, cols, 0.0);
+
+        for zone in zones {
+            for_each_overlapping_pixel(&zone.zone, rows, cols, |row, col| factors.set(row, col, factors.get(row, col).max(zone.factor)));
+        }
+
+        Self{ factors }
+    }
+
+    // The factor of the pixel, `None` outside of all the penalty zones
+    pub fn factor_at(&self, coord: Coord<i16>) -> Option<f64> {
+        if coord.x < 0 || coord.y < 0 || coord.x as usize >= self.factors.cols() || coord.y as usize >= self.factors.rows() {
+            return None
+        }
+
+        match self.factors.at(coord) {
+            factor if factor > 0.0 => Some(factor),
+            _ => None
+        }
+    }
+
+    pub fn smallest_factor(&self) -> Option<f64> {
+        self.factors.values().iter().copied().filter(|factor| *factor > 0.0).min_by(|a, b| a.total_cmp(b))
+    }
+
+    // The factors on the map downsampled by `factor`, the largest one of the pixels of every coarse pixel
+    pub fn downsample(&self, factor: usize) -> Self {
+        let mut factors: Grid<f64> = Grid::new(self.factors.rows().div_ceil(factor), self.factors.cols().div_ceil(factor), 0.0);
+
+        for row in 0..self.factors.rows() {
+            for col in 0..self.factors.cols() {
+                factors.set(row / factor, col / factor, factors.get(row / factor, col / factor).max(self.factors.get(row, col)));
+            }
+        }
+
+        Self{ factors }
+    }
+}
+
+// Reads zones either as a JSON list of pixel-space zones, or as GeoJSON (a FeatureCollection or a single
+// Feature with Polygon or MultiPolygon geometries) which needs a georeference to be placed on the map.
+// GeoJSON zones are named after the `name` property of their feature.
+pub fn read_zones<R: Read>(reader: R, georef: Option<&Georeference>) -> io::Result<Vec<Zone>> {
+    let value: Value = serde_json::from_reader(reader).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+    if value.is_array() {
+        return serde_json::from_value(value).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    Ok(read_features(&value, georef)?.into_iter().map(|(zone, _)| zone).collect())
+}
+
+// Same as `read_zones`, every zone needs a positive `factor` (a property of GeoJSON features)
+pub fn read_penalty_zones<R: Read>(reader: R, georef: Option<&Georeference>) -> io::Result<Vec<PenaltyZone>> {
+    let value: Value = serde_json::from_reader(reader).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+    let zones: Vec<PenaltyZone> = if value.is_array() {
+        serde_json::from_value(value).map_err(|e| Error::new(ErrorKind::InvalidData, e))?
+    } else {
+        read_features(&value, georef)?.into_iter()
+            .map(|(zone, properties)| match properties["factor"].as_f64() {
+                Some(factor) => Ok(PenaltyZone{ zone, factor }),
+                None => Err(invalid_zones("a penalty zone has no factor"))
+            })
+            .collect::<io::Result<Vec<PenaltyZone>>>()?
+    };
+
+    if zones.iter().any(|z| z.factor <= 0.0) {
+        return Err(invalid_zones("penalty factors must be positive"));
+    }
+
+    Ok(zones)
+}
+
+// The zones of GeoJSON features with the properties of their feature
+fn read_features(value: &Value, georef: Option<&Georeference>) -> io::Result<Vec<(Zone, Value)>> {
+    let georef = georef.ok_or_else(|| invalid_zones("GeoJSON zones need a georeference"))?;
+
+    let features = match value["type"].as_str() {
+        Some("FeatureCollection") => value["features"].as_array().ok_or_else(|| invalid_zones("no features"))?.clone(),
+        Some("Feature") => vec![value.clone()],
+        _ => return Err(invalid_zones("expected a FeatureCollection or a Feature"))
+    };
+
+    let mut zones = vec![];
+
+    for (i, feature) in features.iter().enumerate() {
+        let name = feature["properties"]["name"].as_str().map(|n| n.to_string()).unwrap_or_else(|| format!("zone {}", i));
+        let geometry = &feature["geometry"];
+
+        let polygons = match geometry["type"].as_str() {
+            Some("Polygon") => vec![&geometry["coordinates"]],
+            Some("MultiPolygon") => geometry["coordinates"].as_array().ok_or_else(|| invalid_zones("no coordinates"))?.iter().collect(),
+            _ => return Err(invalid_zones("only Polygon and MultiPolygon geometries are supported"))
+        };
+
+        for polygon in polygons {
+            let mut rings = polygon.as_array().ok_or_else(|| invalid_zones("no coordinates"))?
+                .iter()
+                .map(|ring| geojson_ring(ring, georef))
+                .collect::<io::Result<Vec<Vec<Coord<f64>>>>>()?;
+
+            if rings.is_empty() {
+                return Err(invalid_zones("empty polygon"));
+            }
+
+            let outline = rings.remove(0);
+            zones.push((Zone{ name: name.clone(), polygon: outline, holes: rings }, feature["properties"].clone()));
+        }
+    }
+
+    Ok(zones)
+}
+
+// A ring of [longitude, latitude] positions in pixel coordinates
+fn geojson_ring(ring: &Value, georef: &Georeference) -> io::Result<Vec<Coord<f64>>> {
+    ring.as_array().ok_or_else(|| invalid_zones("a ring is not a list of positions"))?
+        .iter()
+        .map(|position| match (position[0].as_f64(), position[1].as_f64()) {
+            (Some(lon), Some(lat)) => {
+                let (x, y) = georef.pixel_of(lat, lon);
+                Ok(Coord{x, y})
+            },
+            _ => Err(invalid_zones("a position is not a [longitude, latitude] pair"))
+        })
+        .collect()
+}
+
+fn invalid_zones(reason: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Invalid zones: {}", reason))
+}